@@ -0,0 +1,169 @@
+use std::fmt;
+
+use crate::analysis::cooling::{cht_channels, cht_rate};
+use crate::analysis::{channel_limits, Limit};
+use crate::data::{installed_channels, CHANNEL_NAMES};
+use crate::file::Flight;
+use crate::headers::{num_cyls, ConfiguredLimits};
+
+// how the EDM decides to alarm. a reading has to stay past its limit for `delay_secs` before the
+// alarm comes on, and then come back inside the limit by the hysteresis before it goes off again,
+// so a reading sitting right on the limit doesn't flash the alarm on and off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AlarmSettings {
+    pub delay_secs: u32,
+    pub hysteresis: i16, // degrees for temperatures, DIF and shock cooling
+    pub volts_hysteresis: i16, // tenths of a volt
+}
+
+impl Default for AlarmSettings {
+    fn default() -> AlarmSettings {
+        AlarmSettings { delay_secs: 10, hysteresis: 5, volts_hysteresis: 2 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlarmSource {
+    Channel(usize),
+    Dif { engine: usize },
+    ShockCooling { engine: usize }, // the fastest cooling CHT, in °/min
+}
+
+impl fmt::Display for AlarmSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            AlarmSource::Channel(c) => f.write_str(CHANNEL_NAMES[c]),
+            AlarmSource::Dif { engine: 0 } => f.write_str("DIF"),
+            AlarmSource::Dif { .. } => f.write_str("RDIF"),
+            AlarmSource::ShockCooling { engine: 0 } => f.write_str("CLD"),
+            AlarmSource::ShockCooling { .. } => f.write_str("RCLD"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Alarm {
+    pub source: AlarmSource,
+    pub limit: Limit,
+    pub start_sample: usize, // where the alarm came on, after the delay
+    pub end_sample: Option<usize>, // where it went off, None if it was still on when the flight ended
+    pub worst: i16,
+}
+
+impl fmt::Display for Alarm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.limit {
+            Limit::Above(limit) => write!(f, "{} HIGH, limit {}, worst {}", self.source, limit, self.worst),
+            Limit::Below(limit) => write!(f, "{} LOW, limit {}, worst {}", self.source, limit, self.worst),
+        }
+    }
+}
+
+// the alarms a pilot would have seen, in the order they came on
+pub fn replay(flight: &Flight, engines: u32, limits: &ConfiguredLimits, settings: &AlarmSettings) -> Vec<Alarm> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let mut checks = Vec::new();
+
+    for (channel, limit) in channel_limits(limits, engines) {
+        if installed.contains(&channel) {
+            let values = flight.records.iter()
+                .map(|r| Some(r.data.values()[channel]).filter(|_| r.available(channel)))
+                .collect::<Vec<_>>();
+            let hysteresis = if channel == 20 { settings.volts_hysteresis } else { settings.hysteresis };
+            checks.push((AlarmSource::Channel(channel), limit, hysteresis, values));
+        }
+    }
+
+    if num_cyls(flight.header.flags) > 0 {
+        let chts = cht_channels(engines).into_iter().filter(|c| installed.contains(c)).collect::<Vec<_>>();
+        for engine in 0..engines as usize {
+            let dif = flight.records.iter().map(|r| Some(r.dif[engine])).collect();
+            checks.push((AlarmSource::Dif { engine }, Limit::Above(limits.dif as i16), settings.hysteresis, dif));
+
+            let rates = chts.iter().filter(|&&c| (c < 24) == (engine == 0)).map(|&c| cht_rate(flight, c)).collect::<Vec<_>>();
+            let cooling = (0..flight.records.len())
+                .map(|s| rates.iter().filter_map(|r| r[s]).map(|r| (-r).round() as i16).max())
+                .collect();
+            checks.push((AlarmSource::ShockCooling { engine }, Limit::Above(limits.cld as i16), settings.hysteresis, cooling));
+        }
+    }
+
+    let elapsed = flight.elapsed_secs();
+    let mut alarms = Vec::new();
+    for (source, limit, hysteresis, values) in checks {
+        let cleared = |v: i16| match limit {
+            Limit::Above(l) => v <= l - hysteresis,
+            Limit::Below(l) => v >= l + hysteresis,
+        };
+
+        let mut past_limit_since: Option<usize> = None;
+        let mut on: Option<Alarm> = None;
+        for (s, value) in values.iter().enumerate() {
+            // a missing reading leaves the alarm as it was
+            let value = match value {
+                Some(v) => *v,
+                None => continue
+            };
+
+            if let Some(alarm) = on.as_mut() {
+                if cleared(value) {
+                    alarm.end_sample = Some(s);
+                    alarms.extend(on.take());
+                    past_limit_since = None;
+                } else if limit.exceeded(value) {
+                    alarm.worst = match limit {
+                        Limit::Above(_) => alarm.worst.max(value),
+                        Limit::Below(_) => alarm.worst.min(value),
+                    };
+                }
+                continue;
+            }
+
+            if !limit.exceeded(value) {
+                past_limit_since = None;
+                continue;
+            }
+            let since = *past_limit_since.get_or_insert(s);
+            if elapsed[s] - elapsed[since] >= settings.delay_secs {
+                let past = values[since..=s].iter().flatten().copied();
+                let worst = match limit {
+                    Limit::Above(_) => past.max(),
+                    Limit::Below(_) => past.min(),
+                }.unwrap_or(value);
+                on = Some(Alarm { source, limit, start_sample: s, end_sample: None, worst });
+            }
+        }
+        alarms.extend(on);
+    }
+
+    alarms.sort_by_key(|a| a.start_sample);
+    alarms
+}
+
+#[test]
+fn test_replay() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let mut records = Vec::new();
+    let mut record = binary_record::new(&TEST_CONFIG);
+    record.data.oil = 180;
+    record.data.bat = 140;
+    record.data.t1 = 1400;
+    record.data.t2 = 1400;
+    record.data.oat = 60;
+    // C1 touches the limit for one sample, then sits over it and dithers just under it
+    for &cht in [380, 420, 380, 420, 420, 420, 418, 414, 416, 409, 380].iter() {
+        record.data.cht = [380; 6];
+        record.data.cht[0] = cht;
+        records.push(record);
+    }
+    let flight = test_decoded_flight(records);
+
+    let limits = ConfiguredLimits { volts_hi_times_ten: 155, volts_lo_times_ten: 130, dif: 400, cht: 415, cld: 60,
+                                    tit: 1650, oil_hi: 220, oil_lo: 75 };
+    let alarms = replay(&flight, 1, &limits, &AlarmSettings::default());
+    assert_eq!(alarms, vec![Alarm {
+        source: AlarmSource::Channel(8), limit: Limit::Above(415), start_sample: 5, end_sample: Some(9), worst: 420
+    }]);
+}
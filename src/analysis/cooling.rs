@@ -0,0 +1,116 @@
+use crate::data::{installed_channels, CHANNEL_NAMES};
+use crate::file::Flight;
+use crate::headers::ConfiguredLimits;
+
+// the CHT channels of each engine, in CHANNEL_NAMES
+pub fn cht_channels(engines: u32) -> Vec<usize> {
+    (8..14).chain(32..38).take(6 * engines as usize).collect()
+}
+
+// °/min for every sample of one CHT, negative while cooling. each sample's rate is the change
+// since the sample before, taken as the median of it and the changes either side so a reading
+// that jumps for one sample and comes back doesn't count, while a real drop keeps its full rate
+// from the sample it started. None where there's no change to go by
+pub fn cht_rate(flight: &Flight, channel: usize) -> Vec<Option<f64>> {
+    let elapsed = flight.elapsed_secs();
+    let reading = |i: usize| Some(flight.records[i].data.values()[channel] as f64).filter(|_| flight.records[i].available(channel));
+    let step = (0..flight.records.len()).map(|i| {
+        let secs = elapsed[i].checked_sub(elapsed[i.checked_sub(1)?]).filter(|&s| s > 0)?;
+        Some((reading(i)? - reading(i - 1)?) * 60.0 / secs as f64)
+    }).collect::<Vec<_>>();
+
+    (0..step.len()).map(|i| {
+        step[i]?;
+        let mut around = [i.checked_sub(1), Some(i), Some(i + 1)].iter()
+            .filter_map(|&j| *step.get(j?)?)
+            .collect::<Vec<_>>();
+        around.sort_by(|a, b| a.total_cmp(b));
+        Some(match around.len() {
+            3 => around[1],
+            _ => around.iter().sum::<f64>() / around.len() as f64,
+        })
+    }).collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShockCooling {
+    pub channel: usize, // the CHT channel in CHANNEL_NAMES
+    pub start_sample: usize,
+    pub end_sample: usize,
+    pub duration_secs: u32,
+    pub worst_rate: f64, // fastest cooling seen, °/min as a positive number
+}
+
+impl std::fmt::Display for ShockCooling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} cooling up to {:.0}°/min for {}s", CHANNEL_NAMES[self.channel], self.worst_rate, self.duration_secs)
+    }
+}
+
+// stretches where a cylinder cooled faster than the $A shock cooling limit, by cylinder then time
+pub fn shock_cooling(flight: &Flight, engines: u32, limits: &ConfiguredLimits) -> Vec<ShockCooling> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let elapsed = flight.elapsed_secs();
+    let limit = limits.cld as f64;
+    let mut events = Vec::new();
+
+    for channel in cht_channels(engines).into_iter().filter(|c| installed.contains(c)) {
+        let rates = cht_rate(flight, channel);
+        let mut current: Option<ShockCooling> = None;
+
+        for (sample, rate) in rates.iter().enumerate() {
+            match rate.map(|r| -r).filter(|&r| r > limit) {
+                Some(cooling) => {
+                    let event = current.get_or_insert(ShockCooling {
+                        channel, start_sample: sample, end_sample: sample, duration_secs: 0, worst_rate: cooling
+                    });
+                    event.end_sample = sample;
+                    event.worst_rate = event.worst_rate.max(cooling);
+                }
+                None => events.extend(current.take()),
+            }
+        }
+        events.extend(current.take());
+    }
+
+    // each sample stands for one interval of flight
+    for event in &mut events {
+        event.duration_secs = elapsed[event.end_sample] - elapsed[event.start_sample] + flight.header.interval_secs as u32;
+    }
+    events
+}
+
+#[test]
+fn test_shock_cooling() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let mut records = Vec::new();
+    let mut record = binary_record::new(&TEST_CONFIG);
+    record.data.cht = [380; 6];
+    for s in 0..40 {
+        if (20..25).contains(&s) {
+            record.data.cht[2] -= 10; // C3 drops 50° in 30 seconds
+        }
+        if s == 30 {
+            record.data.cht[3] = 340; // and C4 reads wrong for one sample
+        } else {
+            record.data.cht[3] = 380;
+        }
+        records.push(record);
+    }
+    let flight = test_decoded_flight(records);
+
+    let rates = cht_rate(&flight, 10);
+    assert_eq!(rates[0], None);
+    assert_eq!(rates[5], Some(0.0));
+    assert_eq!((rates[19], rates[20], rates[24], rates[25]), (Some(0.0), Some(-100.0), Some(-100.0), Some(0.0)));
+
+    let limits = ConfiguredLimits { cld: 40, ..Default::default() };
+    let events = shock_cooling(&flight, 1, &limits);
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].channel, 10);
+    assert_eq!((events[0].start_sample, events[0].end_sample), (20, 24));
+    assert_eq!(events[0].duration_secs, 30);
+    assert_eq!(events[0].worst_rate, 100.0);
+}
@@ -0,0 +1,177 @@
+use std::fmt;
+
+use crate::data::{installed_channels, CHANNEL_NAMES};
+use crate::file::Flight;
+
+// a reading held to the degree for this long while a sibling probe moved is a probe that stopped
+// reading, not a steady engine
+const STUCK_SECS: u32 = 900;
+const STUCK_SIBLING_SWING: i16 = 20;
+// losing a channel this many times in one flight is a loose connection rather than a bad sample
+const DROPOUT_COUNT: usize = 3;
+
+// what a working probe can plausibly read, in raw channel units: the lowest and highest readings
+// and the largest change from one sample to the next (None where fast changes are normal)
+struct Bounds {
+    channels: &'static [usize],
+    min: i16,
+    max: i16,
+    step: Option<i16>,
+    stuck: bool, // noisy enough that a flat line means something
+}
+
+const BOUNDS: &[Bounds] = &[
+    Bounds { channels: &[0, 1, 2, 3, 4, 5, 24, 25, 26, 27, 28, 29], min: -40, max: 2000, step: Some(400), stuck: true }, // EGT
+    Bounds { channels: &[6, 7], min: -40, max: 2000, step: Some(400), stuck: true }, // TIT
+    Bounds { channels: &[8, 9, 10, 11, 12, 13, 32, 33, 34, 35, 36, 37], min: -40, max: 700, step: Some(60), stuck: true }, // CHT
+    Bounds { channels: &[15, 39], min: -40, max: 350, step: Some(30), stuck: false }, // oil
+    Bounds { channels: &[18, 19, 42, 43], min: -40, max: 400, step: Some(60), stuck: false }, // CDT, IAT
+    Bounds { channels: &[21], min: -80, max: 150, step: Some(40), stuck: false }, // OAT
+    Bounds { channels: &[20], min: 0, max: 350, step: Some(50), stuck: false }, // volts in tenths
+    Bounds { channels: &[23, 47], min: 0, max: 800, step: None, stuck: false }, // gph in tenths
+    Bounds { channels: &[40], min: 0, max: 600, step: None, stuck: false }, // inHg in tenths
+    Bounds { channels: &[41], min: 0, max: 4000, step: None, stuck: false }, // RPM
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultKind {
+    Jump { from: i16, to: i16 },
+    Stuck { value: i16 },
+    Dropouts { count: usize },
+    OutOfRange { worst: i16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SensorFault {
+    pub channel: usize,
+    pub kind: FaultKind,
+    pub start_sample: usize,
+    pub end_sample: usize, // inclusive
+}
+
+impl fmt::Display for SensorFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = CHANNEL_NAMES[self.channel];
+        match self.kind {
+            FaultKind::Jump { from, to } => write!(f, "{} jumped from {} to {}", name, from, to),
+            FaultKind::Stuck { value } => write!(f, "{} stuck at {} for {} samples", name, value, self.end_sample - self.start_sample + 1),
+            FaultKind::Dropouts { count } => write!(f, "{} dropped out {} times", name, count),
+            FaultKind::OutOfRange { worst } => write!(f, "{} read an impossible {}", name, worst),
+        }
+    }
+}
+
+// probes that look broken rather than engines that look unhappy, by channel then sample
+pub fn sensor_faults(flight: &Flight, engines: u32) -> Vec<SensorFault> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let stuck_samples = (STUCK_SECS / (flight.header.interval_secs as u32).max(1)).max(2) as usize;
+    let readings = |c: usize| flight.records.iter()
+        .map(|r| Some(r.data.values()[c]).filter(|_| r.available(c)))
+        .collect::<Vec<_>>();
+    let mut faults = Vec::new();
+
+    for &channel in &installed {
+        let values = readings(channel);
+        let bounds = BOUNDS.iter().find(|b| b.channels.contains(&channel));
+
+        // every time a channel that was reporting stops
+        let dropouts = (1..values.len()).filter(|&s| values[s - 1].is_some() && values[s].is_none()).collect::<Vec<_>>();
+        if dropouts.len() >= DROPOUT_COUNT {
+            faults.push(SensorFault {
+                channel, kind: FaultKind::Dropouts { count: dropouts.len() },
+                start_sample: dropouts[0], end_sample: *dropouts.last().unwrap()
+            });
+        }
+
+        let bounds = match bounds {
+            Some(bounds) => bounds,
+            None => continue
+        };
+
+        let mut s = 0;
+        while s < values.len() {
+            let outside = |v: &Option<i16>| v.is_some_and(|v| v < bounds.min || v > bounds.max);
+            if !outside(&values[s]) {
+                s += 1;
+                continue;
+            }
+            let start = s;
+            while s + 1 < values.len() && outside(&values[s + 1]) {
+                s += 1;
+            }
+            let run = values[start..=s].iter().flatten();
+            let worst = run.max_by_key(|&&v| if v > bounds.max { v - bounds.max } else { bounds.min - v }).copied().unwrap();
+            faults.push(SensorFault { channel, kind: FaultKind::OutOfRange { worst }, start_sample: start, end_sample: s });
+            s += 1;
+        }
+
+        if let Some(step) = bounds.step {
+            for s in 1..values.len() {
+                if let (Some(from), Some(to)) = (values[s - 1], values[s]) {
+                    if (to as i32 - from as i32).abs() > step as i32 {
+                        faults.push(SensorFault { channel, kind: FaultKind::Jump { from, to }, start_sample: s - 1, end_sample: s });
+                    }
+                }
+            }
+        }
+
+        if bounds.stuck {
+            let siblings = bounds.channels.iter()
+                .filter(|&&c| c != channel && installed.contains(&c) && (c < 24) == (channel < 24))
+                .map(|&c| readings(c))
+                .collect::<Vec<_>>();
+            let mut s = 0;
+            while s < values.len() {
+                let value = match values[s] {
+                    Some(v) => v,
+                    None => { s += 1; continue; }
+                };
+                let start = s;
+                while s + 1 < values.len() && values[s + 1] == Some(value) {
+                    s += 1;
+                }
+                let moved = siblings.iter().any(|sibling| {
+                    let run = sibling[start..=s].iter().flatten();
+                    let (lo, hi) = run.fold((i16::MAX, i16::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+                    hi >= lo && hi - lo > STUCK_SIBLING_SWING
+                });
+                if s + 1 - start >= stuck_samples && moved {
+                    faults.push(SensorFault { channel, kind: FaultKind::Stuck { value }, start_sample: start, end_sample: s });
+                }
+                s += 1;
+            }
+        }
+    }
+
+    faults.sort_by_key(|f| (f.channel, f.start_sample));
+    faults
+}
+
+#[test]
+fn test_sensor_faults() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let mut records = Vec::new();
+    let mut record = binary_record::new(&TEST_CONFIG);
+    for s in 0..200i16 {
+        record.data.egt = [1300 + s % 40; 6];
+        record.data.egt[1] = 1320; // E2 flat while the rest wander
+        record.data.cht = [350; 6];
+        record.data.cht[3] = if s == 50 { 900 } else { 350 }; // C4 spikes past anything real
+        record.data.oil = 180;
+        record.naflags = [0; 6];
+        if s % 20 == 0 && s > 0 {
+            record.naflags[1] |= 1 << 7; // OIL drops out every 20 samples
+        }
+        records.push(record);
+    }
+    let flight = test_decoded_flight(records);
+
+    let faults = sensor_faults(&flight, 1);
+    let find = |channel: usize| faults.iter().filter(|f| f.channel == channel).map(|f| f.kind).collect::<Vec<_>>();
+    assert_eq!(find(1), vec![FaultKind::Stuck { value: 1320 }]);
+    assert_eq!(find(11), vec![FaultKind::Jump { from: 350, to: 900 }, FaultKind::OutOfRange { worst: 900 }, FaultKind::Jump { from: 900, to: 350 }]);
+    assert_eq!(find(15), vec![FaultKind::Dropouts { count: 9 }]);
+    assert!(find(0).is_empty());
+}
@@ -0,0 +1,99 @@
+use crate::data::{channel_scale, installed_channels};
+use crate::file::Flight;
+
+// FF and USD for each engine
+const FUEL_CHANNELS: [(usize, usize); 2] = [(23, 22), (47, 46)];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FuelUsed {
+    pub engine: usize, // 0 for the left or only engine
+    pub integrated: f64, // gallons, fuel flow summed over the time between samples
+    pub recorded: Option<f64>, // gallons, how far the USD totalizer moved over the flight
+}
+
+impl FuelUsed {
+    // positive when fuel flow adds up to more than the totalizer counted
+    pub fn discrepancy(&self) -> Option<f64> {
+        self.recorded.map(|recorded| self.integrated - recorded)
+    }
+
+    // what the pilot saw on the EDM, which is what a K-factor correction has to go by
+    pub fn indicated(&self) -> f64 {
+        self.recorded.unwrap_or(self.integrated)
+    }
+}
+
+// fuel used by each engine with fuel flow installed. flow is integrated with the trapezoid rule
+// between neighbouring samples, a sample where FF wasn't available only counts through its
+// neighbour. repeat records are already expanded into samples, so the time between samples is
+// the flight's interval throughout
+pub fn fuel_used(flight: &Flight, engines: u32) -> Vec<FuelUsed> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let elapsed = flight.elapsed_secs();
+    let mut used = Vec::new();
+
+    for (engine, &(ff, usd)) in FUEL_CHANNELS.iter().enumerate().take(engines as usize) {
+        if !installed.contains(&ff) {
+            continue;
+        }
+        let reading = |s: usize, c: usize| {
+            let r = &flight.records[s];
+            Some(r.data.values()[c] as f64 / channel_scale(c)).filter(|_| r.available(c))
+        };
+
+        let integrated = (1..flight.records.len()).map(|s| {
+            let hours = (elapsed[s] - elapsed[s - 1]) as f64 / 3600.0;
+            let gph = match (reading(s - 1, ff), reading(s, ff)) {
+                (Some(a), Some(b)) => (a + b) / 2.0,
+                (a, b) => a.or(b).unwrap_or(0.0)
+            };
+            gph * hours
+        }).sum();
+
+        let totals = (0..flight.records.len()).filter_map(|s| reading(s, usd)).collect::<Vec<_>>();
+        let recorded = match (totals.first(), totals.last()) {
+            (Some(first), Some(last)) if installed.contains(&usd) => Some(last - first),
+            _ => None
+        };
+
+        used.push(FuelUsed { engine, integrated, recorded });
+    }
+    used
+}
+
+// the K-factor that would have made the EDM show `actual` gallons where it showed `indicated`,
+// in the same hundredths as $F. JPI's correction is new K = old K * indicated / actual
+pub fn corrected_k_factor(k_factor: u16, indicated: f64, actual: f64) -> Option<u16> {
+    if indicated <= 0.0 || actual <= 0.0 {
+        return None;
+    }
+    let corrected = (k_factor as f64 * indicated / actual).round();
+    if corrected < 1.0 || corrected > u16::MAX as f64 {
+        return None;
+    }
+    Some(corrected as u16)
+}
+
+#[test]
+fn test_fuel_used() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let mut records = Vec::new();
+    let mut record = binary_record::new(&TEST_CONFIG);
+    record.data.ff = 120; // 12 gph
+    for s in 0..601 {
+        record.data.usd = 20 + s / 30; // a tenth of a gallon every 3 minutes is only 2 gph
+        records.push(record);
+    }
+    let flight = test_decoded_flight(records);
+
+    let used = fuel_used(&flight, 1);
+    assert_eq!(used.len(), 1);
+    assert!((used[0].integrated - 12.0).abs() < 1e-9); // an hour at 12 gph
+    assert!((used[0].recorded.unwrap() - 2.0).abs() < 1e-9);
+    assert!((used[0].discrepancy().unwrap() - 10.0).abs() < 1e-9);
+
+    assert_eq!(corrected_k_factor(3183, 40.0, 42.0), Some(3031));
+    assert_eq!(corrected_k_factor(3183, 40.0, 0.0), None);
+}
@@ -0,0 +1,186 @@
+use crate::data::{channel_scale, installed_channels, CHANNEL_NAMES};
+use crate::file::Flight;
+
+const FF: usize = 23;
+const RFF: usize = 47;
+
+// a lean find has to pull fuel flow down by at least this much (gph)
+const MIN_FF_DROP: f64 = 1.0;
+// fuel flow going back up by more than this (gph) from one sample to the next ends a lean find
+const FF_NOISE: f64 = 0.1;
+// so does this long (s) without fuel flow reaching a new low, which is how flat cruise looks
+const MAX_STALL_SECS: u32 = 30;
+// an EGT has to climb and then fall by this much (°F) around its highest reading to count as peaked
+const MIN_EGT_SWING: i16 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CylinderPeak {
+    pub channel: usize, // the EGT channel in CHANNEL_NAMES
+    pub sample: usize,
+    pub egt: i16,
+    pub ff: f64, // gph when the EGT peaked
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LeanFind {
+    pub engine: usize, // 0 for the left or only engine
+    pub start_sample: usize,
+    pub end_sample: usize,
+    pub start_ff: f64,
+    pub end_ff: f64,
+    pub peaks: Vec<CylinderPeak>, // in the order they peaked
+}
+
+impl LeanFind {
+    // the fuel flow between the first and last cylinder to peak, in gph
+    pub fn gami_spread(&self) -> Option<f64> {
+        match (self.peaks.first(), self.peaks.last()) {
+            (Some(first), Some(last)) if self.peaks.len() > 1 => Some(first.ff - last.ff),
+            _ => None
+        }
+    }
+
+    pub fn rich_of_peak(&self) -> bool {
+        self.peaks.is_empty()
+    }
+}
+
+impl std::fmt::Display for CylinderPeak {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} peaked at {} at {:.1} gph", CHANNEL_NAMES[self.channel], self.egt, self.ff)
+    }
+}
+
+// stretches of steadily falling fuel flow, with where each cylinder's EGT peaked along the way.
+// leaning heats the EGTs, so a stretch has to have a cylinder peak or every EGT go up, which
+// keeps out pulling the power back for descent. a stretch where nothing peaked stayed rich of
+// peak the whole time
+pub fn lean_finds(flight: &Flight, engines: u32) -> Vec<LeanFind> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let mut finds = Vec::new();
+
+    for engine in 0..engines as usize {
+        let (ff_channel, egts) = if engine == 0 { (FF, 0..6) } else { (RFF, 24..30) };
+        if !installed.contains(&ff_channel) {
+            continue;
+        }
+        let egts = egts.filter(|c| installed.contains(c)).collect::<Vec<_>>();
+        let ff = flight.records.iter()
+            .map(|r| Some(r.data.values()[ff_channel] as f64 / channel_scale(ff_channel)).filter(|_| r.available(ff_channel)))
+            .collect::<Vec<_>>();
+
+        let elapsed = flight.elapsed_secs();
+        let mut i = 0;
+        while i < ff.len() {
+            // grow a window for as long as fuel flow keeps going down, ending it at the last new low
+            let start = i;
+            let (mut lowest, mut previous) = match ff[i] {
+                Some(v) => (v, v),
+                None => { i += 1; continue; }
+            };
+            let (mut end, mut j) = (i, i);
+            while let Some(Some(v)) = ff.get(j + 1) {
+                if *v > previous + FF_NOISE {
+                    break;
+                }
+                j += 1;
+                previous = *v;
+                if *v < lowest {
+                    lowest = *v;
+                    end = j;
+                } else if elapsed[j] - elapsed[end] > MAX_STALL_SECS {
+                    break;
+                }
+            }
+            i = end + 1;
+
+            let (start_ff, end_ff) = (ff[start].unwrap(), ff[end].unwrap());
+            if start_ff - end_ff < MIN_FF_DROP {
+                continue;
+            }
+
+            let mut peaks = egts.iter().filter_map(|&c| {
+                let readings = (start..=end)
+                    .filter(|&s| flight.records[s].available(c))
+                    .map(|s| (s, flight.records[s].data.values()[c]))
+                    .collect::<Vec<_>>();
+                let &(sample, egt) = readings.iter().max_by_key(|(s, egt)| (*egt, -(*s as i64)))?;
+                let before = readings.iter().take_while(|(s, _)| *s < sample).map(|(_, e)| *e).min()?;
+                let after = readings.iter().skip_while(|(s, _)| *s <= sample).map(|(_, e)| *e).min()?;
+                if egt - before < MIN_EGT_SWING || egt - after < MIN_EGT_SWING {
+                    return None;
+                }
+                Some(CylinderPeak { channel: c, sample, egt, ff: ff[sample]? })
+            }).collect::<Vec<_>>();
+            peaks.sort_by_key(|p| p.sample);
+
+            let egt = |s: usize, c: usize| Some(flight.records[s].data.values()[c]).filter(|_| flight.records[s].available(c));
+            let warmed = !egts.is_empty() && egts.iter().all(|&c| match (egt(start, c), egt(end, c)) {
+                (Some(before), Some(after)) => after - before >= MIN_EGT_SWING,
+                _ => false
+            });
+            if peaks.is_empty() && !warmed {
+                continue;
+            }
+
+            finds.push(LeanFind { engine, start_sample: start, end_sample: end, start_ff, end_ff, peaks });
+        }
+    }
+
+    finds
+}
+
+#[test]
+fn test_lean_find() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let mut records = Vec::new();
+    let mut record = binary_record::new(&TEST_CONFIG);
+    for s in 0..30i16 {
+        record.data.ff = 180 - s * 3; // 18.0 gph leaning to 9.3
+        for (c, egt) in record.data.egt.iter_mut().enumerate() {
+            let peak_at = 10 + c as i16 * 2; // E1 peaks first, E6 last
+            *egt = 1450 - (s - peak_at).abs() * 8;
+        }
+        records.push(record);
+    }
+    let flight = test_decoded_flight(records);
+
+    let finds = lean_finds(&flight, 1);
+    assert_eq!(finds.len(), 1);
+    assert_eq!(finds[0].peaks.len(), 6);
+    assert_eq!(finds[0].peaks[0].channel, 0);
+    assert_eq!(finds[0].peaks[5].sample, 20);
+    assert!((finds[0].gami_spread().unwrap() - 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_not_lean_find() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let flight = |samples: &[(i16, i16)]| {
+        let records = samples.iter().map(|&(ff, egt)| {
+            let mut record = binary_record::new(&TEST_CONFIG);
+            record.data.ff = ff;
+            record.data.egt = [egt; 6];
+            record
+        }).collect();
+        test_decoded_flight(records)
+    };
+
+    // an hour of cruise with fuel flow wandering a tenth either way and drifting down
+    let cruise = (0..600).map(|s| (120 - s / 60 + [0, 1, 0, -1][s as usize % 4], 1400)).collect::<Vec<_>>();
+    assert_eq!(lean_finds(&flight(&cruise), 1), vec![]);
+
+    // pulling the power back for descent takes fuel flow down and the EGTs with it
+    let descent = (0..20).map(|s| (120 - s * 3, 1400 - s * 10)).collect::<Vec<_>>();
+    assert_eq!(lean_finds(&flight(&descent), 1), vec![]);
+
+    // leaning that stays rich of peak still counts
+    let rich = (0..20).map(|s| (140 - s * 2, 1300 + s * 4)).collect::<Vec<_>>();
+    let finds = lean_finds(&flight(&rich), 1);
+    assert_eq!(finds.len(), 1);
+    assert!(finds[0].rich_of_peak());
+}
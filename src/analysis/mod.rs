@@ -0,0 +1,79 @@
+// analyses over decoded flights. they all work on raw channel values and use naflags to skip
+// samples where a probe wasn't reporting
+
+pub mod alarms;
+pub mod cooling;
+pub mod faults;
+pub mod fuel;
+pub mod lean;
+pub mod phases;
+pub mod power;
+pub mod trend;
+
+use crate::data::{installed_channels, CHANNEL_NAMES};
+use crate::file::Flight;
+use crate::headers::ConfiguredLimits;
+
+// a value worked out from the recorded channels, one per sample and None where it couldn't be
+#[derive(Clone, Debug, PartialEq)]
+pub struct DerivedChannel {
+    pub name: String,
+    pub values: Vec<Option<f64>>,
+}
+
+// what can be asked for by name in exports
+pub const DERIVED_CHANNELS: &[&str] = &["cht-rate", "percent-power"];
+
+// the named derived channels of a flight. the columns depend only on the names and engine count,
+// so flights from one download line up even when they had different probes installed. `model`
+// is only needed to estimate percent power where HP isn't recorded
+pub fn derived_channels(flight: &Flight, engines: u32, names: &[&str], model: Option<&power::EngineModel>)
+                        -> Result<Vec<DerivedChannel>, String> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let mut derived = Vec::new();
+    for &name in names {
+        match name {
+            "cht-rate" => derived.extend(cooling::cht_channels(engines).into_iter().map(|c| DerivedChannel {
+                name: format!("{}_RATE", CHANNEL_NAMES[c]),
+                values: if installed.contains(&c) { cooling::cht_rate(flight, c) } else { vec![None; flight.records.len()] },
+            })),
+            "percent-power" => derived.push(DerivedChannel {
+                name: "PCT_HP".to_owned(),
+                values: power::percent_power(flight, engines, model),
+            }),
+            _ => return Err(format!("unknown derived channel {}, expected one of {}", name, DERIVED_CHANNELS.join(", ")))
+        }
+    }
+    Ok(derived)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Limit {
+    Above(i16),
+    Below(i16),
+}
+
+impl Limit {
+    pub fn exceeded(self, value: i16) -> bool {
+        match self {
+            Limit::Above(limit) => value > limit,
+            Limit::Below(limit) => value < limit,
+        }
+    }
+}
+
+// the $A limits that apply to a single channel, in raw channel units. shock cooling and DIF
+// aren't readings of one channel and are checked elsewhere
+pub fn channel_limits(limits: &ConfiguredLimits, engines: u32) -> Vec<(usize, Limit)> {
+    let mut checks = Vec::new();
+    for engine in 0..engines as usize {
+        let (chts, tits, oil) = if engine == 0 { (8..14, [6, 7], 15) } else { (32..38, [30, 31], 39) };
+        checks.extend(chts.map(|c| (c, Limit::Above(limits.cht as i16))));
+        checks.extend(tits.iter().map(|&c| (c, Limit::Above(limits.tit as i16))));
+        checks.push((oil, Limit::Above(limits.oil_hi as i16)));
+        checks.push((oil, Limit::Below(limits.oil_lo as i16)));
+    }
+    checks.push((20, Limit::Above(limits.volts_hi_times_ten as i16)));
+    checks.push((20, Limit::Below(limits.volts_lo_times_ten as i16)));
+    checks
+}
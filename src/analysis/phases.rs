@@ -0,0 +1,234 @@
+use std::fmt;
+
+use crate::analysis::power::{percent_power, EngineModel};
+use crate::analysis::{channel_limits, Limit};
+use crate::data::installed_channels;
+use crate::file::Flight;
+use crate::headers::ConfiguredLimits;
+use crate::summary::{channel_stats, mean_and_max, ChannelStats};
+
+// the EDM doesn't record altitude, so phases come from the engine alone, each channel as a
+// fraction of its highest reading in the flight. the EDM's percent HP is the best measure of
+// power where a single has it, after that fuel flow tells climb from cruise power best,
+// RPM tells taxiing from a runup or an approach best, and each falls back on the others when
+// it isn't installed. the left engine decides for twins. only one takeoff and landing per
+// flight is recognised, so touch and goes read as one long flight
+const POWER_CHANNELS: [usize; 4] = [30, 23, 41, 40]; // HP, FF, RPM, MAP
+const GROUND_CHANNELS: [usize; 3] = [41, 23, 40];
+
+// power is averaged over this long to keep single noisy samples from flipping the phase
+const SMOOTH_SECS: u32 = 30;
+// full power, a takeoff starts the first time power gets here
+const TAKEOFF_POWER: f64 = 0.95;
+// how long into full power still counts as the takeoff rather than the climb
+const TAKEOFF_SECS: u32 = 60;
+// the climb lasts until power drops below this
+const CLIMB_POWER: f64 = 0.85;
+// on the ground, anything at or above this is a runup rather than taxiing
+const RUNUP_POWER: f64 = 0.6;
+// after takeoff, staying below this for the rest of the flight means it has landed
+const GROUND_POWER: f64 = 0.5;
+// the descent starts once power stays below this fraction of the typical cruise power
+const DESCENT_POWER: f64 = 0.85;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    Taxi,
+    Runup,
+    Takeoff,
+    Climb,
+    Cruise,
+    Descent,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Taxi => "taxi",
+            Phase::Runup => "runup",
+            Phase::Takeoff => "takeoff",
+            Phase::Climb => "climb",
+            Phase::Cruise => "cruise",
+            Phase::Descent => "descent",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub phase: Phase,
+    pub start_sample: usize,
+    pub end_sample: usize, // inclusive
+}
+
+// the phase of every sample. a flight without any power channel is all taxi
+pub fn sample_phases(flight: &Flight, engines: u32) -> Vec<Phase> {
+    let n = flight.records.len();
+    let mut phases = vec![Phase::Taxi; n];
+    let (power, ground) = match (smoothed(flight, engines, &POWER_CHANNELS), smoothed(flight, engines, &GROUND_CHANNELS)) {
+        (Some(power), Some(ground)) => (power, ground),
+        _ => return phases
+    };
+
+    let takeoff = match power.iter().position(|&p| p >= TAKEOFF_POWER) {
+        Some(takeoff) => takeoff,
+        None => {
+            mark_ground(&mut phases, &ground, 0..n);
+            return phases;
+        }
+    };
+    let landing = (takeoff..n).rev().find(|&i| ground[i] >= GROUND_POWER).unwrap_or(takeoff);
+    mark_ground(&mut phases, &ground, 0..takeoff);
+
+    let takeoff_samples = (TAKEOFF_SECS / (flight.header.interval_secs as u32).max(1)).max(1) as usize;
+    let mut i = takeoff;
+    while i <= landing && i < takeoff + takeoff_samples && power[i] >= CLIMB_POWER {
+        phases[i] = Phase::Takeoff;
+        i += 1;
+    }
+    while i <= landing && power[i] >= CLIMB_POWER {
+        phases[i] = Phase::Climb;
+        i += 1;
+    }
+
+    // whatever is left in the air is cruise until power comes off for good
+    let mut cruise = power[i..landing + 1].to_vec();
+    cruise.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let typical = cruise.get(cruise.len() / 2).copied().unwrap_or(0.0);
+    let descent = (i..=landing).rev().find(|&s| power[s] >= typical * DESCENT_POWER).map_or(i, |s| s + 1);
+    for (s, phase) in phases.iter_mut().enumerate().take(landing + 1).skip(i) {
+        *phase = if s < descent { Phase::Cruise } else { Phase::Descent };
+    }
+
+    phases
+}
+
+// runs of samples in the same phase, in order
+pub fn segments(flight: &Flight, engines: u32) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    for (sample, phase) in sample_phases(flight, engines).into_iter().enumerate() {
+        match segments.last_mut() {
+            Some(last) if last.phase == phase => last.end_sample = sample,
+            _ => segments.push(Segment { phase, start_sample: sample, end_sample: sample })
+        }
+    }
+    segments
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Exceedance {
+    pub channel: usize,
+    pub limit: Limit,
+    pub worst: i16, // the furthest past the limit it got
+    pub samples: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhaseReport {
+    pub segment: Segment,
+    pub channels: Vec<ChannelStats>,
+    pub exceedances: Vec<Exceedance>,
+    pub percent_power: Option<(f64, f64)>, // mean and max, see power::percent_power
+}
+
+// statistics for each phase of a flight, and the $A limits broken during it when there are any
+pub fn phase_reports(flight: &Flight, engines: u32, limits: Option<&ConfiguredLimits>, model: Option<&EngineModel>)
+                     -> Vec<PhaseReport> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let power = percent_power(flight, engines, model);
+    let checks = limits.map(|l| channel_limits(l, engines)).unwrap_or_default();
+
+    segments(flight, engines).into_iter().map(|segment| {
+        let range = segment.start_sample..segment.end_sample + 1;
+        let exceedances = checks.iter().filter(|(c, _)| installed.contains(c)).filter_map(|&(channel, limit)| {
+            let over = flight.records[range.clone()].iter()
+                .filter(|r| r.available(channel))
+                .map(|r| r.data.values()[channel])
+                .filter(|&v| limit.exceeded(v))
+                .collect::<Vec<_>>();
+            let worst = match limit {
+                Limit::Above(_) => over.iter().max(),
+                Limit::Below(_) => over.iter().min(),
+            };
+            worst.map(|&worst| Exceedance { channel, limit, worst, samples: over.len() })
+        }).collect();
+
+        PhaseReport {
+            segment,
+            percent_power: mean_and_max(&power[range.clone()]),
+            channels: channel_stats(flight, engines, range),
+            exceedances
+        }
+    }).collect()
+}
+
+fn mark_ground(phases: &mut [Phase], ground: &[f64], samples: std::ops::Range<usize>) {
+    for s in samples {
+        phases[s] = if ground[s] >= RUNUP_POWER { Phase::Runup } else { Phase::Taxi };
+    }
+}
+
+// the first installed channel of `channels` as a fraction of its highest reading, averaged over
+// SMOOTH_SECS. samples where it wasn't available carry the last reading forward
+fn smoothed(flight: &Flight, engines: u32, channels: &[usize]) -> Option<Vec<f64>> {
+    let installed = installed_channels(flight.header.flags, engines);
+    // on twins channel 30 is the right engine's TIT rather than HP
+    let channel = channels.iter().copied().find(|&c| installed.contains(&c) && (c != 30 || engines == 1))?;
+
+    let mut last = 0.0;
+    let raw = flight.records.iter().map(|r| {
+        if r.available(channel) {
+            last = r.data.values()[channel] as f64;
+        }
+        last
+    }).collect::<Vec<_>>();
+    let max = raw.iter().copied().fold(0.0, f64::max);
+    if max <= 0.0 {
+        return None;
+    }
+
+    let half = (SMOOTH_SECS / 2 / (flight.header.interval_secs as u32).max(1)) as usize;
+    Some((0..raw.len()).map(|i| {
+        let window = &raw[i.saturating_sub(half)..(i + half + 1).min(raw.len())];
+        window.iter().sum::<f64>() / window.len() as f64 / max
+    }).collect())
+}
+
+#[test]
+fn test_phases() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let mut records = Vec::new();
+    let mut record = binary_record::new(&TEST_CONFIG);
+    // fuel flow in tenths of a gph for each stretch of one minute samples
+    for &(ff, minutes) in [(30, 5), (110, 2), (30, 2), (180, 10), (120, 60), (100, 10), (30, 5)].iter() {
+        record.data.ff = ff;
+        record.data.cht = [if ff == 180 { 420 } else { 350 }; 6];
+        for _ in 0..minutes {
+            records.push(record);
+        }
+    }
+    let mut flight = test_decoded_flight(records);
+    flight.header.interval_secs = 60;
+
+    let phases = segments(&flight, 1).iter().map(|s| (s.phase, s.start_sample, s.end_sample)).collect::<Vec<_>>();
+    assert_eq!(phases, vec![
+        (Phase::Taxi, 0, 4),
+        (Phase::Runup, 5, 6),
+        (Phase::Taxi, 7, 8),
+        (Phase::Takeoff, 9, 9),
+        (Phase::Climb, 10, 18),
+        (Phase::Cruise, 19, 78),
+        (Phase::Descent, 79, 88),
+        (Phase::Taxi, 89, 93),
+    ]);
+
+    let limits = ConfiguredLimits { cht: 400, oil_hi: 250, volts_hi_times_ten: 300, tit: 1650, ..Default::default() };
+    let reports = phase_reports(&flight, 1, Some(&limits), None);
+    let climb = &reports[4];
+    assert_eq!(climb.segment.phase, Phase::Climb);
+    assert_eq!(climb.exceedances.len(), 6);
+    assert_eq!(climb.exceedances[0], Exceedance { channel: 8, limit: Limit::Above(400), worst: 420, samples: 9 });
+    assert!(reports[5].exceedances.is_empty());
+}
@@ -0,0 +1,159 @@
+use nom::IResult;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{char, space0};
+use nom::combinator::all_consuming;
+use nom::number::complete::double;
+use nom::sequence::{delimited, separated_pair};
+
+use crate::analysis::lean::lean_finds;
+use crate::data::{channel_scale, installed_channels};
+use crate::file::Flight;
+
+const HP: usize = 30; // the EDM's own percent horsepower, singles only
+const FF: usize = 23;
+const MAP: usize = 40;
+const RPM: usize = 41;
+
+// what percent power is measured against. read from a file of `key = value` lines, # starts a
+// comment:
+//
+//     # Lycoming IO-540
+//     rated_hp = 300
+//     rated_rpm = 2700
+//     rated_map = 29.6
+//     hp_per_gph = 14.9
+//     rich_hp_per_gph = 12
+//
+// rated_hp is required, the rest default to the values above
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EngineModel {
+    pub rated_hp: f64,
+    pub rated_rpm: f64,
+    pub rated_map: f64, // inHg at full throttle and rated RPM
+    pub hp_per_gph: f64, // power per gallon an hour lean of peak, about 14.9 for Lycoming and 13.7 for Continental
+    pub rich_hp_per_gph: f64, // and rich of peak, about 12 at best power (0.5 lb/hp/hr of avgas)
+}
+
+fn setting(i: &str) -> IResult<&str, (&str, f64)> {
+    let key = take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_');
+    all_consuming(delimited(space0, separated_pair(key, delimited(space0, char('='), space0), double), space0))(i)
+}
+
+impl EngineModel {
+    pub fn parse(text: &str) -> Result<EngineModel, String> {
+        let mut rated_hp = None;
+        let mut model = EngineModel { rated_hp: 0.0, rated_rpm: 2700.0, rated_map: 29.6, hp_per_gph: 14.9, rich_hp_per_gph: 12.0 };
+
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (_, (key, value)) = setting(line).map_err(|_| format!("line {}: expected key = number", n + 1))?;
+            if value <= 0.0 {
+                return Err(format!("line {}: {} has to be positive", n + 1, key));
+            }
+            match key {
+                "rated_hp" => rated_hp = Some(value),
+                "rated_rpm" => model.rated_rpm = value,
+                "rated_map" => model.rated_map = value,
+                "hp_per_gph" => model.hp_per_gph = value,
+                "rich_hp_per_gph" => model.rich_hp_per_gph = value,
+                _ => return Err(format!("line {}: unknown setting {}", n + 1, key))
+            }
+        }
+
+        model.rated_hp = rated_hp.ok_or("rated_hp is missing")?;
+        Ok(model)
+    }
+}
+
+// percent of rated power for every sample of the left or only engine. singles with the HP option
+// installed record it directly. otherwise it's estimated from the model, and without one it's
+// unknown. an engine makes the lesser of what its air (RPM times MAP) and its fuel (horsepower
+// per gph) allow, so the estimate takes whichever is lower, falling back on the one that's
+// available, and never goes past rated power. fuel makes less power rich of peak, so the lean of
+// peak factor is only used where the EGTs have shown the engine is lean
+pub fn percent_power(flight: &Flight, engines: u32, model: Option<&EngineModel>) -> Vec<Option<f64>> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let reading = |r: &crate::data::binary_record, c: usize| {
+        Some(r.data.values()[c] as f64 / channel_scale(c)).filter(|_| installed.contains(&c) && r.available(c))
+    };
+
+    let lean = lean_of_peak(flight, engines);
+
+    flight.records.iter().zip(lean).map(|(r, lean)| {
+        if engines == 1 && installed.contains(&HP) {
+            return reading(r, HP);
+        }
+        let model = model?;
+        let air = match (reading(r, RPM), reading(r, MAP)) {
+            (Some(rpm), Some(map)) => Some(model.rated_hp * rpm * map / (model.rated_rpm * model.rated_map)),
+            _ => None
+        };
+        let fuel = reading(r, FF).map(|ff| ff * if lean { model.hp_per_gph } else { model.rich_hp_per_gph });
+        let hp = match (air, fuel) {
+            (Some(air), Some(fuel)) => air.min(fuel),
+            (air, fuel) => air.or(fuel)?
+        };
+        Some(hp.min(model.rated_hp) * 100.0 / model.rated_hp)
+    }).collect()
+}
+
+// samples the left or only engine is known to be lean of peak: from the last cylinder to peak in
+// a lean find where they all did, for as long as fuel flow stays at or below where it peaked
+fn lean_of_peak(flight: &Flight, engines: u32) -> Vec<bool> {
+    let installed = installed_channels(flight.header.flags, engines);
+    let egts = (0..6).filter(|c| installed.contains(c)).count();
+    let mut lean = vec![false; flight.records.len()];
+
+    for find in lean_finds(flight, engines).iter().filter(|f| f.engine == 0 && egts > 0 && f.peaks.len() == egts) {
+        let last = find.peaks[egts - 1];
+        for (s, r) in flight.records.iter().enumerate().skip(last.sample) {
+            if !r.available(FF) || r.data.values()[FF] as f64 / channel_scale(FF) > last.ff {
+                break;
+            }
+            lean[s] = true;
+        }
+    }
+
+    lean
+}
+
+#[test]
+fn test_percent_power() {
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let model = EngineModel::parse("# IO-540\nrated_hp = 300\nhp_per_gph=15 # rounder\n\n").unwrap();
+    assert_eq!(model, EngineModel { rated_hp: 300.0, rated_rpm: 2700.0, rated_map: 29.6, hp_per_gph: 15.0, rich_hp_per_gph: 12.0 });
+    assert!(EngineModel::parse("rated_rpm = 2700").is_err());
+    assert!(EngineModel::parse("rated_hp 300").is_err());
+
+    let mut record = binary_record::new(&TEST_CONFIG);
+    record.data.ff = 150; // 15 gph is 225 hp of fuel
+    record.data.rpm = 2700;
+    record.data.map = 148; // half of rated MAP is 150 hp of air
+    let mut flight = test_decoded_flight(vec![record]);
+    flight.header.flags = 0x1C39F8FD; // with MAP and RPM
+
+    assert_eq!(percent_power(&flight, 1, None), vec![None]);
+    assert_eq!(percent_power(&flight, 1, Some(&model)), vec![Some(50.0)]);
+    flight.header.flags = 0x1831F8FD; // FF only
+    assert_eq!(percent_power(&flight, 1, Some(&model)), vec![Some(60.0)]); // not known to be lean
+    flight.records[0].data.ff = 300;
+    assert_eq!(percent_power(&flight, 1, Some(&model)), vec![Some(100.0)]);
+
+    // leaning through peak like test_lean_find, where the last cylinder peaks at 12 gph
+    flight.records = (0..30i16).map(|s| {
+        let mut record = binary_record::new(&TEST_CONFIG);
+        record.data.ff = 180 - s * 3;
+        for (c, egt) in record.data.egt.iter_mut().enumerate() {
+            *egt = 1450 - (s - (10 + c as i16 * 2)).abs() * 8;
+        }
+        record
+    }).collect();
+    let power = percent_power(&flight, 1, Some(&model));
+    assert_eq!(power[5], Some(16.5 * 12.0 / 3.0));
+    assert_eq!(power[25], Some(10.5 * 15.0 / 3.0));
+}
@@ -0,0 +1,130 @@
+use crate::analysis::phases::{segments, Phase};
+use crate::data::{DateTime, CHANNEL_NAMES};
+use crate::file::Flight;
+use crate::summary::channel_stats;
+
+// a cylinder has drifted once its reading relative to the other cylinders on the same engine
+// moved by this much (°F) between the older and newer half of the flights
+const EGT_DRIFT: f64 = 40.0;
+const CHT_DRIFT: f64 = 20.0;
+// fewer flights than this can't tell a drift from a one off
+const MIN_TREND_FLIGHTS: usize = 4;
+
+// the averages of one flight's longest cruise. readings are (channel, mean) for each installed
+// cylinder that was available, both engines' cylinders together on twins
+#[derive(Clone, Debug, PartialEq)]
+pub struct CruiseAverages {
+    pub flight_number: u16,
+    pub start: DateTime,
+    pub cruise_secs: u32,
+    pub egt: Vec<(usize, f64)>,
+    pub cht: Vec<(usize, f64)>,
+    pub oil: Option<f64>,
+}
+
+impl CruiseAverages {
+    // hottest minus coolest cylinder mean, over every engine
+    pub fn egt_spread(&self) -> Option<f64> {
+        spread(&self.egt)
+    }
+
+    pub fn cht_spread(&self) -> Option<f64> {
+        spread(&self.cht)
+    }
+}
+
+fn spread(readings: &[(usize, f64)]) -> Option<f64> {
+    let max = readings.iter().map(|(_, v)| *v).fold(None, |m: Option<f64>, v| Some(m.map_or(v, |m| m.max(v))))?;
+    let min = readings.iter().map(|(_, v)| *v).fold(max, f64::min);
+    Some(max - min)
+}
+
+// None when the flight never reached cruise
+pub fn cruise_averages(flight: &Flight, engines: u32) -> Option<CruiseAverages> {
+    let cruise = segments(flight, engines).into_iter()
+        .filter(|s| s.phase == Phase::Cruise)
+        .max_by_key(|s| s.end_sample - s.start_sample)?;
+    let stats = channel_stats(flight, engines, cruise.start_sample..cruise.end_sample + 1);
+    let means = |channels: &[std::ops::Range<usize>]| stats.iter()
+        .filter(|s| channels.iter().any(|r| r.contains(&s.channel)))
+        .map(|s| (s.channel, s.mean))
+        .collect::<Vec<_>>();
+
+    Some(CruiseAverages {
+        flight_number: flight.info.flight_number,
+        start: flight.header.start(),
+        cruise_secs: (cruise.end_sample - cruise.start_sample + 1) as u32 * flight.header.interval_secs as u32,
+        egt: means(&[0..6, 24..30]),
+        cht: means(&[8..14, 32..38]),
+        oil: stats.iter().find(|s| s.channel == 15).map(|s| s.mean),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Drift {
+    pub channel: usize,
+    pub before: f64, // mean difference from the engine's other cylinders over the older flights
+    pub after: f64, // the same over the newer flights
+}
+
+impl std::fmt::Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} went from {:+.0} to {:+.0} against the other cylinders", CHANNEL_NAMES[self.channel], self.before, self.after)
+    }
+}
+
+// cylinders whose cruise EGT or CHT moved away from (or toward) their siblings, the sign of a
+// sticking valve or a clogging injector rather than a change in how the engine was flown.
+// `flights` should be in the order they were flown
+pub fn drifting(flights: &[CruiseAverages]) -> Vec<Drift> {
+    if flights.len() < MIN_TREND_FLIGHTS {
+        return Vec::new();
+    }
+    let (older, newer) = flights.split_at(flights.len() / 2);
+
+    let mut drifts = Vec::new();
+    for (channels, threshold, egt) in [(0..6, EGT_DRIFT, true), (24..30, EGT_DRIFT, true),
+                                       (8..14, CHT_DRIFT, false), (32..38, CHT_DRIFT, false)].iter() {
+        for channel in channels.clone() {
+            let deviation = |half: &[CruiseAverages]| {
+                let offsets = half.iter().filter_map(|f| {
+                    let readings = if *egt { &f.egt } else { &f.cht };
+                    let engine = readings.iter().filter(|(c, _)| channels.contains(c)).collect::<Vec<_>>();
+                    let own = engine.iter().find(|(c, _)| *c == channel)?.1;
+                    let others = engine.iter().filter(|(c, _)| *c != channel).map(|(_, v)| *v).collect::<Vec<_>>();
+                    if others.is_empty() {
+                        return None;
+                    }
+                    Some(own - others.iter().sum::<f64>() / others.len() as f64)
+                }).collect::<Vec<_>>();
+                if offsets.is_empty() { None } else { Some(offsets.iter().sum::<f64>() / offsets.len() as f64) }
+            };
+
+            if let (Some(before), Some(after)) = (deviation(older), deviation(newer)) {
+                if (after - before).abs() > *threshold {
+                    drifts.push(Drift { channel, before, after });
+                }
+            }
+        }
+    }
+    drifts
+}
+
+#[test]
+fn test_drifting() {
+    let flights = (0..6).map(|n| CruiseAverages {
+        flight_number: n,
+        start: Default::default(),
+        cruise_secs: 3600,
+        egt: (0..6).map(|c| (c, 1400.0 + if c == 3 && n >= 3 { 60.0 } else { 0.0 })).collect(),
+        cht: (8..14).map(|c| (c, 350.0 + c as f64)).collect(),
+        oil: Some(190.0),
+    }).collect::<Vec<_>>();
+
+    assert_eq!(flights[5].egt_spread(), Some(60.0));
+    assert_eq!(flights[5].cht_spread(), Some(5.0));
+    let drifts = drifting(&flights);
+    assert_eq!(drifts.len(), 1);
+    assert_eq!(drifts[0].channel, 3);
+    assert_eq!((drifts[0].before, drifts[0].after), (0.0, 60.0));
+}
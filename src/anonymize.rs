@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+use crate::data::DateTime;
+use crate::file::JpiFile;
+use crate::headers::{HeaderRecord, Timestamp};
+use crate::writer::rewrite;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateChange {
+    Keep,
+    Shift(i64), // days, can be negative
+    Strip, // everything becomes 2000-01-01 00:00
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anonymize {
+    pub tail_number: String,
+    pub dates: DateChange,
+}
+
+impl Anonymize {
+    // an error if a shift lands outside 2000-2127, which is all the EDM can store
+    fn datetime(&self, original: DateTime) -> io::Result<DateTime> {
+        match self.dates {
+            DateChange::Keep => Ok(original),
+            DateChange::Shift(days) => {
+                let first = DateTime { year: 2000, month: 1, day: 1, ..Default::default() };
+                let last = DateTime { year: 2127, month: 12, day: 31, hour: 23, minute: 59, second: 59 };
+                let shifted = days.checked_mul(86400).and_then(|secs| original.unix_seconds().checked_add(secs))
+                    .filter(|secs| (first.unix_seconds()..=last.unix_seconds()).contains(secs))
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                                                  format!("shifting {} by {} days leaves 2000-2127", original, days)))?;
+                Ok(DateTime::from_unix_seconds(shifted))
+            }
+            DateChange::Strip => Ok(DateTime { year: 2000, month: 1, day: 1, ..Default::default() }),
+        }
+    }
+
+    fn header(&self, record: &HeaderRecord) -> io::Result<HeaderRecord> {
+        Ok(match record {
+            // underscores in place of whatever followed the tail number, so the record keeps its length
+            HeaderRecord::U { padding, .. } => HeaderRecord::U {
+                tail: self.tail_number.clone(),
+                padding: padding.chars().map(|_| '_').collect(),
+            },
+            HeaderRecord::T(t) => {
+                let dt = self.datetime(t.datetime())?;
+                HeaderRecord::T(Timestamp {
+                    month: dt.month,
+                    day: dt.day,
+                    year: dt.year - 2000,
+                    hour: dt.hour,
+                    minute: dt.minute,
+                    unknown: t.unknown,
+                })
+            }
+            HeaderRecord::Extended { record, extra } => HeaderRecord::Extended {
+                record: Box::new(self.header(record)?),
+                extra: extra.clone(),
+            },
+            record => record.clone()
+        })
+    }
+}
+
+// rewrites a download without its identity: the $U tail number is replaced and the $T and flight
+// header dates are shifted or stripped. samples are copied byte for byte
+pub fn anonymize<W: Write>(out: &mut W, file: &JpiFile, options: &Anonymize) -> io::Result<()> {
+    let headers = file.headers().iter().map(|h| options.header(h)).collect::<io::Result<Vec<_>>>()?;
+
+    let indices = (0..file.flights().count()).collect::<Vec<_>>();
+    rewrite(out, file, &headers, &indices, |header| {
+        header.set_start(options.datetime(header.start())?);
+        Ok(())
+    })
+}
+
+#[test]
+fn test_anonymize() {
+    use crate::file::{test_download, test_flight, TEST_RECORDS};
+
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS)])).unwrap();
+    let options = Anonymize { tail_number: "N00000".to_owned(), dates: DateChange::Shift(-10) };
+    let mut bytes = Vec::new();
+    anonymize(&mut bytes, &file, &options).unwrap();
+
+    let anonymized = JpiFile::from_bytes(bytes).unwrap();
+    assert_eq!(anonymized.headers()[0].to_string(), "$U,N00000__*07");
+    let (before, after) = (file.decode_flight(0).unwrap(), anonymized.decode_flight(0).unwrap());
+    assert_eq!(after.records, before.records);
+    assert_eq!(after.header.start().unix_seconds(), before.header.start().unix_seconds() - 10 * 86400);
+
+    // 10000 days before the 2005 test download is 1977, which the EDM can't store
+    for days in [-10000, i64::MIN].iter() {
+        let options = Anonymize { dates: DateChange::Shift(*days), ..options.clone() };
+        let error = anonymize(&mut Vec::new(), &file, &options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{Float64Builder, Int16Builder, StringBuilder, TimestampSecondBuilder, UInt16Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{ArrowError, DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+
+use crate::analysis::DerivedChannel;
+use crate::data::{installed_channels, CHANNEL_NAMES};
+use crate::file::Flight;
+use crate::headers::{num_cyls, ConfigInfo, num_engines};
+
+// one row per sample: tail, flight, timestamp, then every channel installed on any of the
+// flights. a channel reads null where it isn't installed on that flight or naflags says so.
+// `derived` is either empty or holds the same derived channels for each flight, added as
+// float columns after DIF
+pub fn record_batch(flights: &[Flight], derived: &[Vec<DerivedChannel>], config: &ConfigInfo,
+                    tail_number: &str) -> Result<RecordBatch, ArrowError> {
+    let engines = num_engines(config);
+    let mut channels = flights.iter()
+        .flat_map(|f| installed_channels(f.header.flags, engines))
+        .collect::<Vec<_>>();
+    channels.sort_unstable();
+    channels.dedup();
+
+    let rows = flights.iter().map(|f| f.records.len()).sum();
+    let mut tail = StringBuilder::with_capacity(rows, rows * tail_number.len());
+    let mut flight = UInt16Builder::with_capacity(rows);
+    let mut timestamp = TimestampSecondBuilder::with_capacity(rows);
+    let mut values = channels.iter().map(|_| Int16Builder::with_capacity(rows)).collect::<Vec<_>>();
+    let mut dif = (0..engines).map(|_| Int16Builder::with_capacity(rows)).collect::<Vec<_>>();
+    let derived_names = derived.first().map(|d| d.iter().map(|c| c.name.as_str()).collect::<Vec<_>>()).unwrap_or_default();
+    let mut extra = derived_names.iter().map(|_| Float64Builder::with_capacity(rows)).collect::<Vec<_>>();
+    if !derived.is_empty() && (derived.len() != flights.len() || derived.iter().any(|d| d.len() != derived_names.len())) {
+        return Err(ArrowError::InvalidArgumentError("derived channels don't match the flights".to_owned()));
+    }
+
+    for (n, f) in flights.iter().enumerate() {
+        let installed = installed_channels(f.header.flags, engines);
+        let has_cyls = num_cyls(f.header.flags) > 0;
+        let start = f.header.start().unix_seconds();
+
+        for (sample, (record, elapsed)) in f.records.iter().zip(f.elapsed_secs()).enumerate() {
+            tail.append_value(tail_number);
+            flight.append_value(f.info.flight_number);
+            timestamp.append_value(start + elapsed as i64);
+
+            let data = record.data.values();
+            for (builder, &c) in values.iter_mut().zip(channels.iter()) {
+                builder.append_option(Some(data[c]).filter(|_| installed.contains(&c) && record.available(c)));
+            }
+            for (builder, &d) in dif.iter_mut().zip(record.dif.iter()) {
+                builder.append_option(Some(d).filter(|_| has_cyls));
+            }
+            for (builder, channel) in extra.iter_mut().zip(derived.get(n).into_iter().flatten()) {
+                builder.append_option(channel.values.get(sample).copied().flatten());
+            }
+        }
+    }
+
+    let mut fields = vec![
+        Field::new("tail", DataType::Utf8, false),
+        Field::new("flight", DataType::UInt16, false),
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Second, None), false),
+    ];
+    fields.extend(channels.iter().map(|&c| Field::new(CHANNEL_NAMES[c], DataType::Int16, true)));
+    fields.extend(["DIF", "RDIF"].iter().take(engines as usize).map(|n| Field::new(*n, DataType::Int16, true)));
+    fields.extend(derived_names.iter().map(|n| Field::new(*n, DataType::Float64, true)));
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(tail.finish()),
+        Arc::new(flight.finish()),
+        Arc::new(timestamp.finish()),
+    ];
+    columns.extend(values.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+    columns.extend(dif.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+    columns.extend(extra.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+}
+
+// all batches have to share the first one's schema
+pub fn write_parquet<W: Write + Send>(out: W, batches: &[RecordBatch]) -> Result<(), ParquetError> {
+    let schema = match batches.first() {
+        Some(batch) => batch.schema(),
+        None => return Err(ParquetError::General("nothing to write".to_owned()))
+    };
+
+    let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(out, schema, Some(props))?;
+    for batch in batches {
+        writer.write(batch)?;
+    }
+    writer.close()?;
+    Ok(())
+}
+
+#[test]
+fn test_record_batch() {
+    use arrow_array::Array;
+    use crate::data::binary_record;
+    use crate::file::{test_decoded_flight, TEST_CONFIG};
+
+    let config = TEST_CONFIG;
+    let mut record = binary_record::new(&config);
+    record.naflags[0] = 0b10; // E2 not available
+    let flight = test_decoded_flight(vec![record; 3]);
+
+    let batch = record_batch(std::slice::from_ref(&flight), &[], &config, "N51SW").unwrap();
+    assert_eq!(batch.num_rows(), 3);
+    assert!(batch.column_by_name("MAP").is_none()); // not installed
+    assert_eq!(batch.column_by_name("E1").unwrap().null_count(), 0);
+    assert_eq!(batch.column_by_name("E2").unwrap().null_count(), 3);
+
+    let derived = crate::analysis::derived_channels(&flight, 1, &["cht-rate"], None).unwrap();
+    let batch = record_batch(&[flight], &[derived], &config, "N51SW").unwrap();
+    assert_eq!(batch.column_by_name("C1_RATE").unwrap().null_count(), 1); // nothing to compare the first sample to
+}
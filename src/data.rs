@@ -1,31 +1,104 @@
-use std::io::{BufReader, Read};
+use std::fmt;
+use std::io::{Read, Take};
 use std::io;
-use std::fs::{File, read};
 use std::mem::size_of;
-use std::error::Error;
-use nom::error::ParseError;
 use nom::IResult;
 use nom::number::complete as num;
 use nom::bytes::complete as bytes;
 
-use crate::headers::{ConfigInfo, num_cyls, num_engines};
+use crate::headers::*;
 use std::ops::Range;
 use std::cmp::{min, max};
 
 
-#[derive(Clone, Copy, Default, Debug, PartialEq)]
-#[repr(packed)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash)]
+#[repr(C, packed)]
 pub struct flightheader {
-    flightnumber: u16,
-    flags: u32, // not actually in the file as a big endian 32 bit int
-    unknown: u16,
-    interval_secs: u16,
-    datebits: u16,
-    timebits: u16
+    pub flightnumber: u16,
+    pub flags: u32, // not actually in the file as a big endian 32 bit int
+    pub unknown: u16,
+    pub interval_secs: u16,
+    pub datebits: u16,
+    pub timebits: u16
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u16,
+    pub day: u16,
+    pub hour: u16,
+    pub minute: u16,
+    pub second: u16,
+}
+
+impl DateTime {
+    // seconds since the unix epoch, treating the EDM's clock as UTC
+    pub fn unix_seconds(&self) -> i64 {
+        // days_from_civil from http://howardhinnant.github.io/date_algorithms.html
+        let y = self.year as i64 - if self.month <= 2 { 1 } else { 0 };
+        let era = y.div_euclid(400);
+        let yoe = y - era * 400;
+        let m = self.month as i64;
+        let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146097 + doe - 719468;
+
+        days * 86400 + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64
+    }
+
+    pub fn from_unix_seconds(secs: i64) -> DateTime {
+        // civil_from_days, same source as above
+        let days = secs.div_euclid(86400);
+        let time = secs.rem_euclid(86400);
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+
+        DateTime {
+            year: (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as u16,
+            month: month as u16,
+            day: (doy - (153 * mp + 2) / 5 + 1) as u16,
+            hour: (time / 3600) as u16,
+            minute: (time / 60 % 60) as u16,
+            second: (time % 60) as u16,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+impl flightheader {
+    // date is dddddmmmmyyyyyyy from the low bit up, time is 2 second units then minutes then hours
+    pub fn start(&self) -> DateTime {
+        DateTime {
+            year: 2000 + (self.datebits >> 9),
+            month: (self.datebits >> 5) & 0xF,
+            day: self.datebits & 0x1F,
+            hour: self.timebits >> 11,
+            minute: (self.timebits >> 5) & 0x3F,
+            second: (self.timebits & 0x1F) * 2,
+        }
+    }
+
+    // the EDM can only store 2000-2127 and even seconds, years outside that are clamped
+    pub fn set_start(&mut self, start: DateTime) {
+        self.datebits = (start.year.clamp(2000, 2127) - 2000) << 9 | (start.month & 0xF) << 5 | (start.day & 0x1F);
+        self.timebits = (start.hour & 0x1F) << 11 | (start.minute & 0x3F) << 5 | ((start.second / 2) & 0x1F);
+    }
 }
 
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
+#[repr(C)] // as_array depends on the fields staying in this order
 pub struct data_record {
     // first byte of flags
     pub egt: [i16; 6],
@@ -68,6 +141,100 @@ pub struct data_record {
     pub rff: i16
 }
 
+// names of the data_record fields in as_array order, the "HP" and "RCDT" slots are unions
+pub const CHANNEL_NAMES: [&str; 48] = [
+    "E1", "E2", "E3", "E4", "E5", "E6", "T1", "T2",
+    "C1", "C2", "C3", "C4", "C5", "C6", "CLD", "OIL",
+    "MARK", "UNK_3_1", "CDT", "IAT", "BAT", "OAT", "USD", "FF",
+    "RE1", "RE2", "RE3", "RE4", "RE5", "RE6", "HP", "RT2",
+    "RC1", "RC2", "RC3", "RC4", "RC5", "RC6", "RCLD", "ROIL",
+    "MAP", "RPM", "RCDT", "RIAT", "UNK_6_4", "UNK_6_5", "RUSD", "RFF",
+];
+
+// what to divide a raw value by to get units: volts, gph, gallons and inHg are stored in tenths
+pub fn channel_scale(channel: usize) -> f64 {
+    match CHANNEL_NAMES[channel] {
+        "BAT" | "FF" | "USD" | "MAP" | "RFF" | "RUSD" => 10.0,
+        _ => 1.0
+    }
+}
+
+pub fn channel_index(name: &str) -> Option<usize> {
+    CHANNEL_NAMES.iter().position(|n| n.eq_ignore_ascii_case(name))
+}
+
+// a comma separated list of channel names where "E1-E6" covers everything between two names
+pub fn parse_channel_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut channels = Vec::new();
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let index = |name: &str| channel_index(name).ok_or_else(|| format!("unknown channel {}", name));
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (index(from)?, index(to)?);
+                if from > to {
+                    return Err(format!("{} is backwards", part));
+                }
+                channels.extend(from..=to);
+            }
+            None => channels.push(index(part)?),
+        }
+    }
+    Ok(channels)
+}
+
+// which slots of the data_record a set of feature flags says are wired up
+pub fn installed_channels(flags: u32, engines: u32) -> Vec<usize> {
+    let cyls = num_cyls(flags) as usize;
+    let twin = engines == 2;
+    let has = |flag: u32| flags & flag == flag;
+
+    let left = [
+        (6, has(FLAG_TIT)), (7, has(FLAG_TIT2)), (14, has(FLAG_CLD)), (15, has(FLAG_OIL)),
+        (16, true), (18, has(FLAG_CDT)), (19, has(FLAG_IAT)), (20, has(FLAG_BAT)),
+        (21, has(FLAG_OAT)), (22, has(FLAG_FF)), (23, has(FLAG_FF)),
+        (30, has(if twin { FLAG_TIT } else { FLAG_HP })), (40, has(FLAG_MAP)), (41, has(FLAG_RPM)),
+    ];
+    let right = [
+        (31, has(FLAG_TIT2)), (38, has(FLAG_CLD)), (39, has(FLAG_OIL)), (42, has(FLAG_CDT)),
+        (43, has(FLAG_IAT)), (46, has(FLAG_FF)), (47, has(FLAG_FF)),
+    ];
+
+    let mut channels = Vec::new();
+    // egt and cht by cylinder, and again for the right engine of a twin
+    for bank in if twin { &[0usize, 24][..] } else { &[0usize][..] } {
+        channels.extend((0..cyls.min(6)).map(|c| bank + c));
+        channels.extend((0..cyls.min(6)).map(|c| bank + 8 + c));
+    }
+    channels.extend(left.iter().filter(|(_, on)| *on).map(|(idx, _)| *idx));
+    if twin {
+        channels.extend(right.iter().filter(|(_, on)| *on).map(|(idx, _)| *idx));
+    }
+    channels.sort_unstable();
+    channels
+}
+
+// how a flight's feature flags differ from the $C flags, e.g. a probe added after the flight
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FlagDifference {
+    pub added: u32, // set for the flight but not in $C
+    pub removed: u32, // set in $C but not for the flight
+}
+
+impl FlagDifference {
+    pub fn new(config: &ConfigInfo, header: &flightheader) -> FlagDifference {
+        let config = config_flags(config);
+        let flight = header.flags;
+        FlagDifference {
+            added: flight & !config,
+            removed: config & !flight,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0
+    }
+}
+
 const TWINJUMP: u32 = 3 * 8; // offset from egt to regt
 
 fn has_rpm(header: &flightheader) -> bool {
@@ -79,6 +246,12 @@ impl data_record {
     fn as_array(&mut self) -> &mut [i16; 48] {
         unsafe { std::mem::transmute(self) }
     }
+
+    // the fields in CHANNEL_NAMES order
+    pub fn values(&self) -> [i16; 48] {
+        let mut copy = *self;
+        *copy.as_array()
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -104,6 +277,11 @@ impl binary_record {
         }
     }
 
+    // `channel` indexes CHANNEL_NAMES
+    pub fn available(&self, channel: usize) -> bool {
+        !test_bit(self.naflags[channel / 8], (channel % 8) as u32)
+    }
+
     // im just pasting the reference impl lol
     pub fn calcstuff(&mut self, config: &ConfigInfo, header: &flightheader) {
         let cyls = num_cyls(header.flags);
@@ -123,7 +301,7 @@ impl binary_record {
         }
 
         if has_rpm(header) {
-            self.data.rpm += (self.data.rpm_highbyte_rcdt << 8);
+            self.data.rpm += self.data.rpm_highbyte_rcdt << 8;
             self.data.rpm_highbyte_rcdt = 0;
         }
     }
@@ -138,27 +316,42 @@ struct data_header {
     repeatcount: u8,
 }
 
+// the smallest record is a bare data header, anything less at the end of a flight is padding to a word boundary
+pub(crate) const MIN_RECORD_LEN: usize = size_of::<data_header>();
+
 fn be_u16_uwu(slice: &[u8]) -> u16 {
     ((slice[0] as u16) << 8) | slice[1] as u16
 }
 
-fn be_u32_uwu(slice: &[u8]) -> u32 {
-    ((slice[0] as u32) << (8 * 3)) |
-    ((slice[1] as u32) << (8 * 2)) |
-    ((slice[2] as u32) << (8 * 1)) |
-    ((slice[3] as u32))
+// how the byte after a flight header or data record is worked out from the bytes before it. which
+// one a download uses goes by its protocol, see headers::Protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    Xor, // the earliest 700 firmware
+    Sum, // the byte sum, until the sign was flipped
+    NegatedSum, // everything since, adding it to the byte sum gives zero
 }
 
-fn calc_new_checksum(data: &[u8]) -> u8 {
-    let sum: u8 = data.iter().fold(0u8, |acc, x| acc.overflowing_add(*x).0);
-    (-(sum as i8)) as u8
-}
+impl Checksum {
+    pub const ALL: [Checksum; 3] = [Checksum::NegatedSum, Checksum::Sum, Checksum::Xor];
+
+    // the first of ALL that `data`, a flight header or record with its checksum byte on the end,
+    // checks out with
+    pub fn detect(data: &[u8]) -> Option<Checksum> {
+        let (stored, body) = data.split_last()?;
+        Checksum::ALL.iter().copied().find(|c| c.calc(body) == *stored)
+    }
 
-fn calc_checksum(data: &[u8]) -> u8 {
-    return calc_new_checksum(data);
+    pub fn calc(self, data: &[u8]) -> u8 {
+        match self {
+            Checksum::Xor => data.iter().fold(0u8, |acc, x| acc ^ x),
+            Checksum::Sum => data.iter().fold(0u8, |acc, x| acc.wrapping_add(*x)),
+            Checksum::NegatedSum => data.iter().fold(0u8, |acc, x| acc.wrapping_add(*x)).wrapping_neg(),
+        }
+    }
 }
 
-pub fn read_flight_header(reader: &mut BufReader<File>) -> io::Result<flightheader> {
+pub fn read_flight_header<R: Read>(reader: &mut R, checksum: Checksum) -> io::Result<flightheader> {
     let mut buf = [0u8; size_of::<flightheader>() + 1];
     reader.read_exact(&mut buf)?;
 
@@ -177,9 +370,9 @@ pub fn read_flight_header(reader: &mut BufReader<File>) -> io::Result<flighthead
     i += 2;
     let timebits = be_u16_uwu(&buf[i..]);
     i += 2;
-    let checksum = buf[i];
-    let computed = calc_checksum(&buf[..size_of::<flightheader>()]);
-    assert_eq!(checksum, computed);
+    if buf[i] != checksum.calc(&buf[..size_of::<flightheader>()]) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("flight {} header checksum mismatch", flightnumber)));
+    }
 
     Ok(flightheader {
         flightnumber,
@@ -191,12 +384,25 @@ pub fn read_flight_header(reader: &mut BufReader<File>) -> io::Result<flighthead
     })
 }
 
+// the inverse of read_flight_header, checksum included
+pub fn write_flight_header(header: &flightheader, checksum: Checksum) -> [u8; size_of::<flightheader>() + 1] {
+    let mut buf = [0u8; size_of::<flightheader>() + 1];
+    let flags = header.flags;
+    let words = [header.flightnumber, flags as u16, (flags >> 16) as u16,
+                 header.unknown, header.interval_secs, header.datebits, header.timebits];
+    for (i, word) in words.iter().enumerate() {
+        buf[i * 2..i * 2 + 2].copy_from_slice(&word.to_be_bytes());
+    }
+    buf[size_of::<flightheader>()] = checksum.calc(&buf[..size_of::<flightheader>()]);
+    buf
+}
+
 fn parse_data_header(i: &[u8]) -> IResult<&[u8], data_header> {
     let (i, decode1) = num::u8(i)?;
     let (i, decode2) = num::u8(i)?;
     let (i, repeat) = num::u8(i)?;
-    if decode1 != decode2 {
-        panic!("mismatched decode bytes") // TODO: remove this
+    if decode1 != decode2 { // these should always be the same
+        return Err(nom::Err::Failure(nom::error::Error::new(i, nom::error::ErrorKind::Verify)))
     }
 
     Ok((i, data_header {
@@ -225,10 +431,6 @@ fn set_bit(x: &mut u8, bit: u32) {
     *x |= 1 << bit;
 }
 
-fn set_bit_slice(arr: &mut [u8], bit: u32) {
-    set_bit(&mut arr[(bit / 8) as usize], bit % 8);
-}
-
 fn parse_decode_bits<'a>(i: &'a[u8], out: &mut [u8], decodeflags: u8, bits: Range<u8>) -> IResult<&'a [u8], ()> {
     let mut i = i;
     for bit in bits.clone() {
@@ -242,14 +444,63 @@ fn parse_decode_bits<'a>(i: &'a[u8], out: &mut [u8], decodeflags: u8, bits: Rang
     Ok((i, ()))
 }
 
-pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &ConfigInfo, fheader: &flightheader) -> IResult<&'a [u8], binary_record> {
-    assert_eq!(((config.feature_flags_hi as u32) << 16 | (config.feature_flags_lo as u32)), fheader.flags);
+// where everything in one binary record is, for looking at the format rather than decoding it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecordLayout {
+    pub decode_flags: [u8; 2],
+    pub repeat: u8,
+    pub field_flags: [u8; 6],
+    pub scale_flags: [u8; 2],
+    pub sign_flags: [u8; 6],
+    pub field_deltas: Vec<(usize, i16)>, // channel and signed change, 0 marks the channel not available
+    pub scale_deltas: Vec<(usize, i16)>, // channel and signed change to the high byte, already shifted
+    pub checksum: Option<(u8, u8)>, // stored and calculated, a repeat record has none
+}
 
+// lays out the record at the start of `input` the same way parse_binary_record reads it, without
+// applying it or checking the checksum
+pub fn record_layout(input: &[u8], checksum: Checksum) -> IResult<&[u8], RecordLayout> {
     let (i, header) = parse_data_header(input)?;
+    let mut layout = RecordLayout { decode_flags: header.decodeflags, repeat: header.repeatcount, ..Default::default() };
     if header.repeatcount != 0 {
-        if header.repeatcount > 1 { // TODO: this isn't handled properly
-            unimplemented!()
+        return Ok((i, layout));
+    }
+
+    let (i, _) = parse_decode_bits(i, &mut layout.field_flags, header.decodeflags[0], 0..6)?;
+    let (i, _) = parse_decode_bits(i, &mut layout.scale_flags, header.decodeflags[0], 6..8)?;
+    let (i, _) = parse_decode_bits(i, &mut layout.sign_flags, header.decodeflags[0], 0..6)?;
+
+    let num_fields = layout.field_flags.iter().map(|x| x.count_ones()).sum::<u32>() as usize;
+    let (i, field_dif) = bytes::take(num_fields)(i)?;
+    let num_scale = layout.scale_flags.iter().map(|x| x.count_ones()).sum::<u32>() as usize;
+    let (i, scale_dif) = bytes::take(num_scale)(i)?;
+
+    let mut difs = field_dif.iter();
+    for (f, flags) in layout.field_flags.iter().enumerate() {
+        for bit in (0..8).filter(|&bit| test_bit(*flags, bit)) {
+            let diff = *difs.next().unwrap() as i16;
+            layout.field_deltas.push((f * 8 + bit as usize, if test_bit(layout.sign_flags[f], bit) { -diff } else { diff }));
+        }
+    }
+    let mut difs = scale_dif.iter();
+    for (f, flags) in layout.scale_flags.iter().enumerate() {
+        for bit in (0..8).filter(|&bit| test_bit(*flags, bit)) {
+            let idx = f as u32 * TWINJUMP + bit;
+            let diff = (*difs.next().unwrap() as i16) << 8;
+            layout.scale_deltas.push((idx as usize, if test_bit_slice(&layout.sign_flags, idx) { diff.wrapping_neg() } else { diff }));
         }
+    }
+
+    let record_size = input.len() - i.len();
+    let (i, stored) = num::u8(i)?;
+    layout.checksum = Some((stored, checksum.calc(&input[..record_size])));
+    Ok((i, layout))
+}
+
+pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &ConfigInfo, fheader: &flightheader, checksum: Checksum) -> IResult<&'a [u8], binary_record> {
+    // the flight's own flags decide what's installed, $C only describes the unit at download time
+    let (i, header) = parse_data_header(input)?;
+    if header.repeatcount != 0 { // callers use record_repeats to know how many samples this stands for
         return Ok((i, *prev));
     }
     let mut field_flags = [0u8; 6];
@@ -259,7 +510,6 @@ pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &C
     let (i, _) = parse_decode_bits(i, &mut field_flags, header.decodeflags[0], 0..6)?;
     let (i, _) = parse_decode_bits(i, &mut scale_flags, header.decodeflags[0], 6..8)?;
     let (i, _) = parse_decode_bits(i, &mut sign_flags,  header.decodeflags[0], 0..6)?;
-    assert!(scale_flags[1] == 0 || num_engines(config) == 1);
 
     let num_fields = field_flags.iter().map(|x| x.count_ones()).sum::<u32>() as usize;
     let (i, field_dif) = bytes::take(num_fields)(i)?;
@@ -267,6 +517,19 @@ pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &C
     let num_scale = scale_flags.iter().map(|x| x.count_ones()).sum::<u32>() as usize;
     let (i, scale_dif) = bytes::take(num_scale)(i)?;
 
+    // nothing in a record that doesn't checksum can be trusted, even to be decoded
+    let record_size = input.len() - i.len();
+    let (rest, stored) = num::u8(i)?;
+    let bad_record = || Err(nom::Err::Failure(nom::error::Error::new(input, nom::error::ErrorKind::Verify)));
+    if stored != checksum.calc(&input[..record_size]) {
+        return bad_record();
+    }
+    // a twin has no second byte of scale flags, and a single's RPM sign covers its high byte too
+    if (scale_flags[1] != 0 && num_engines(config) != 1)
+        || (num_engines(config) == 1 && test_bit(sign_flags[5], 1) && test_bit(sign_flags[5], 2)) {
+        return bad_record();
+    }
+
     let mut out = *prev;
 
     let mut field_dif_idx = 0usize; // index to field_dif and scale_dif
@@ -278,7 +541,7 @@ pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &C
             let sign = test_bit(sign_flags[i], bit);
             let idx = (i * 8) + bit as usize;
             let diff = field_dif[field_dif_idx] as i16; // set low byte
-            if diff != 0 {
+            if diff == 0 { // a flagged field with no change means the value isn't available
                 set_bit(&mut out.naflags[i], bit);
             } else {
                 clear_bit(&mut out.naflags[i], bit);
@@ -298,9 +561,9 @@ pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &C
     }
 
     let mut scale_dif_idx = 0usize;
-    for f in 0..scale_flags.len() {
+    for (f, flags) in scale_flags.iter().enumerate() {
         for bit in 0..8 {
-            if test_bit(scale_flags[f], bit) {
+            if test_bit(*flags, bit) {
                 let idx = f as u32 * TWINJUMP + bit;
                 let mut x = scale_dif[scale_dif_idx] as i16;
                 if x != 0 {
@@ -318,25 +581,212 @@ pub fn parse_binary_record<'a>(prev: &binary_record, input: &'a [u8], config: &C
         }
     }
 
-    if num_engines(config) == 1 {
-        if test_bit(sign_flags[5], 1) { // rpm
-            assert!(!test_bit(sign_flags[5], 2)); // rpm_highbyte
-            out.data.rpm_highbyte_rcdt = -out.data.rpm_highbyte_rcdt;
-            if out.data.rpm_highbyte_rcdt != 0 {
-                clear_bit(&mut out.naflags[5], 1); // rpm
-            }
+    if num_engines(config) == 1 && test_bit(sign_flags[5], 1) { // rpm
+        out.data.rpm_highbyte_rcdt = -out.data.rpm_highbyte_rcdt;
+        if out.data.rpm_highbyte_rcdt != 0 {
+            clear_bit(&mut out.naflags[5], 1); // rpm
         }
     }
     out.calcstuff(config, fheader);
 
-    let end_ptr = i.as_ptr(); // dont want to include the checksum
-    let (i, checksum) = num::u8(i)?;
-    let begin_ptr = input.as_ptr();
-    let record_size = unsafe { end_ptr.offset_from(begin_ptr) } as usize;
-    let all_bytes = unsafe { std::slice::from_raw_parts(begin_ptr, record_size) };
-    let calculated = calc_checksum(all_bytes);
-    assert_eq!(checksum, calculated);
+    Ok((rest, out))
+}
+
+
+// how many samples the record at the start of `input` stands for. a repeat record is only a data
+// header whose count says how many more times the previous record was sampled
+pub fn record_repeats(input: &[u8]) -> usize {
+    match input.get(2) {
+        Some(&count) if count != 0 => count as usize,
+        _ => 1
+    }
+}
+
+pub(crate) fn io_error(e: nom::Err<nom::error::Error<&[u8]>>) -> io::Error {
+    let message = match e {
+        nom::Err::Incomplete(_) => "flight data ends partway through a record".to_owned(),
+        nom::Err::Error(e) | nom::Err::Failure(e) if e.code == nom::error::ErrorKind::Verify =>
+            format!("bad record with {} bytes left in the flight", e.input.len()),
+        nom::Err::Error(e) | nom::Err::Failure(e) =>
+            format!("{:?} with {} bytes left in the flight", e.code, e.input.len()),
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// reads exactly one binary record off of the stream, buffering only that record's bytes. also
+// returns how many samples it stands for, see record_repeats
+pub fn read_binary_record<R: Read>(reader: &mut R, prev: &binary_record, config: &ConfigInfo, fheader: &flightheader, checksum: Checksum) -> io::Result<(binary_record, usize)> {
+    let mut buf = vec![0u8; 3];
+    reader.read_exact(&mut buf)?;
+
+    // a repeat record is nothing but its data header, anything else goes on with flag bytes,
+    // deltas and a checksum
+    if buf[2] == 0 {
+        let decodeflags = buf[0];
+        let num_flags = (decodeflags & 0b111111).count_ones() * 2 + (decodeflags >> 6).count_ones();
+        let start = buf.len();
+        buf.resize(start + num_flags as usize, 0);
+        reader.read_exact(&mut buf[start..])?;
+
+        // flag bytes come in the same order parse_binary_record reads them
+        let mut field_flags = [0u8; 6];
+        let mut scale_flags = [0u8; 2];
+        let (i, _) = parse_decode_bits(&buf[3..], &mut field_flags, decodeflags, 0..6).map_err(io_error)?;
+        parse_decode_bits(i, &mut scale_flags, decodeflags, 6..8).map_err(io_error)?;
+
+        let num_difs = field_flags.iter().chain(scale_flags.iter()).map(|x| x.count_ones()).sum::<u32>();
+        let start = buf.len();
+        buf.resize(start + num_difs as usize + 1, 0); // + checksum
+        reader.read_exact(&mut buf[start..])?;
+    }
+
+    let (_, record) = parse_binary_record(prev, &buf, config, fheader, checksum).map_err(io_error)?;
+    Ok((record, record_repeats(&buf)))
+}
 
-    Ok((i, out))
+// decodes one flight's records incrementally from a stream. `length` is the flight's
+// length from its $D record, which is in 16 bit words and includes the flight header
+pub struct FlightDecoder<R: Read> {
+    reader: Take<R>,
+    config: ConfigInfo,
+    checksum: Checksum,
+    header: flightheader,
+    prev: binary_record,
+    repeats: usize, // copies of `prev` still owed by a repeat record
 }
 
+impl<R: Read> FlightDecoder<R> {
+    pub fn new(reader: R, config: &ConfigInfo, checksum: Checksum, length: u16) -> io::Result<FlightDecoder<R>> {
+        let mut reader = reader.take(length as u64 * 2);
+        let header = read_flight_header(&mut reader, checksum)?;
+
+        Ok(FlightDecoder {
+            reader,
+            config: *config,
+            checksum,
+            header,
+            prev: binary_record::new(config),
+            repeats: 0
+        })
+    }
+
+    pub fn header(&self) -> &flightheader {
+        &self.header
+    }
+
+    pub fn flag_difference(&self) -> FlagDifference {
+        FlagDifference::new(&self.config, &self.header)
+    }
+}
+
+impl<R: Read> Iterator for FlightDecoder<R> {
+    type Item = io::Result<binary_record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.repeats > 0 {
+            self.repeats -= 1;
+            return Some(Ok(self.prev));
+        }
+        if self.reader.limit() < MIN_RECORD_LEN as u64 {
+            return None;
+        }
+
+        match read_binary_record(&mut self.reader, &self.prev, &self.config, &self.header, self.checksum) {
+            Ok((record, repeats)) => {
+                self.prev = record;
+                self.repeats = repeats - 1;
+                Some(Ok(record))
+            }
+            Err(e) => {
+                self.reader.set_limit(0); // don't keep decoding garbage
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<R: Read> Drop for FlightDecoder<R> {
+    // leave the underlying stream at the start of the next flight even if we stopped early
+    fn drop(&mut self) {
+        let _ = io::copy(&mut self.reader, &mut io::sink());
+    }
+}
+
+#[test]
+fn test_flight_decoder() {
+    let config = crate::file::TEST_CONFIG;
+
+    let mut flight = vec![0, 227, 0xF8, 0xFD, 0x18, 0x31, 0, 0, 0, 6, 0, 0, 0, 0];
+    flight.push(Checksum::NegatedSum.calc(&flight));
+    let record = [1u8, 1, 0, 0b1, 0, 10];
+    flight.extend_from_slice(&record);
+    flight.push(Checksum::NegatedSum.calc(&record));
+    flight.extend_from_slice(&[0, 0, 1]); // repeat the previous record
+    flight.extend_from_slice(&[0, 0, 2]); // and twice more
+
+    let mut stream = flight.as_slice();
+    let mut decoder = FlightDecoder::new(&mut stream, &config, Checksum::NegatedSum, (flight.len() / 2) as u16).unwrap();
+    assert_eq!({ decoder.header().flightnumber }, 227);
+
+    let first = decoder.next().unwrap().unwrap();
+    assert_eq!(first.data.egt[0], 0xF0 + 10);
+    for _ in 0..3 {
+        assert_eq!(decoder.next().unwrap().unwrap(), first);
+    }
+    assert!(decoder.next().is_none());
+    drop(decoder);
+    assert!(stream.is_empty());
+}
+
+#[test]
+fn test_record_layout() {
+    let record = [1u8, 1, 0, 0b11, 0b10, 10, 3]; // E1 +10, E2 -3
+    let mut bytes = record.to_vec();
+    bytes.push(Checksum::NegatedSum.calc(&record) ^ 1);
+
+    let (rest, layout) = record_layout(&bytes, Checksum::NegatedSum).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(layout.field_deltas, vec![(0, 10), (1, -3)]);
+    assert_eq!(layout.checksum, Some((Checksum::NegatedSum.calc(&record) ^ 1, Checksum::NegatedSum.calc(&record))));
+    assert_eq!(record_layout(&[0, 0, 4], Checksum::NegatedSum).unwrap().1.repeat, 4);
+    assert_eq!(Checksum::Xor.calc(&[0x0F, 0xF1, 0x02]), 0xFC);
+    assert_eq!(Checksum::Sum.calc(&[0x0F, 0xF1, 0x02]), 0x02);
+    assert_eq!(Checksum::NegatedSum.calc(&[0x0F, 0xF1, 0x02]), 0xFE);
+    assert_eq!(Checksum::detect(&[0x0F, 0xF1, 0x02, 0x02]), Some(Checksum::Sum));
+    assert_eq!(Checksum::detect(&[0x0F, 0xF1, 0x02, 0x00]), None);
+}
+
+#[test]
+fn test_datetime() {
+    let mut header = flightheader { datebits: 0x2C55, timebits: 0x6020, ..Default::default() };
+    let start = header.start();
+    assert_eq!(start, DateTime { year: 2022, month: 2, day: 21, hour: 12, minute: 1, second: 0 });
+    assert_eq!(start.unix_seconds(), 1645444860);
+    assert_eq!(DateTime::from_unix_seconds(start.unix_seconds() - 400 * 86400).to_string(), "2021-01-17 12:01:00");
+
+    header.set_start(start);
+    assert_eq!(({ header.datebits }, { header.timebits }), (0x2C55, 0x6020));
+}
+
+#[test]
+fn test_not_available() {
+    let config = crate::file::TEST_CONFIG;
+    let header = flightheader { flags: 0x1831F8FD, ..Default::default() };
+    let parse = |prev: &binary_record, record: &[u8]| {
+        let mut bytes = record.to_vec();
+        bytes.push(Checksum::NegatedSum.calc(record));
+        parse_binary_record(prev, &bytes, &config, &header, Checksum::NegatedSum).unwrap().1
+    };
+
+    // E1 flagged with no change, E2 changed
+    let first = parse(&binary_record::new(&config), &[1, 1, 0, 0b11, 0, 0, 7]);
+    assert!(!first.available(0));
+    assert!(first.available(1));
+    assert_eq!(first.data.egt[1], 0xF0 + 7);
+
+    // a later change brings E1 back, and E2 stays as it was when it isn't flagged
+    let second = parse(&first, &[1, 1, 0, 0b1, 0, 5]);
+    assert!(second.available(0));
+    assert_eq!(second.data.egt[0], 0xF0 + 5);
+    assert!(second.available(1));
+}
@@ -0,0 +1,167 @@
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+use crate::data::CHANNEL_NAMES;
+use crate::file::Flight;
+use crate::headers::{config_flags, num_engines, tail_number, ConfigInfo, HeaderRecord};
+use crate::summary::summarize;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS aircraft (
+    id INTEGER PRIMARY KEY,
+    tail_number TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS downloads (
+    id INTEGER PRIMARY KEY,
+    aircraft_id INTEGER NOT NULL REFERENCES aircraft(id),
+    downloaded_at INTEGER NOT NULL, -- unix seconds from $T
+    model_number INTEGER NOT NULL,
+    feature_flags INTEGER NOT NULL,
+    unknown_flags INTEGER NOT NULL,
+    firmware_version INTEGER NOT NULL,
+    UNIQUE (aircraft_id, downloaded_at)
+);
+
+CREATE TABLE IF NOT EXISTS flights (
+    id INTEGER PRIMARY KEY,
+    aircraft_id INTEGER NOT NULL REFERENCES aircraft(id),
+    download_id INTEGER NOT NULL REFERENCES downloads(id), -- the first download it was seen in
+    flight_number INTEGER NOT NULL,
+    flags INTEGER NOT NULL,
+    unknown INTEGER NOT NULL,
+    interval_secs INTEGER NOT NULL,
+    start_time INTEGER NOT NULL, -- unix seconds
+    duration_secs INTEGER NOT NULL,
+    samples INTEGER NOT NULL,
+    max_dif INTEGER,
+    max_rdif INTEGER,
+    UNIQUE (aircraft_id, flight_number, start_time)
+);
+
+CREATE TABLE IF NOT EXISTS flight_channels (
+    flight_id INTEGER NOT NULL REFERENCES flights(id),
+    channel TEXT NOT NULL,
+    min INTEGER NOT NULL,
+    max INTEGER NOT NULL,
+    mean REAL NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (flight_id, channel)
+);
+
+CREATE TABLE IF NOT EXISTS samples (
+    flight_id INTEGER NOT NULL REFERENCES flights(id),
+    sample INTEGER NOT NULL,
+    elapsed_secs INTEGER NOT NULL,
+    channel TEXT NOT NULL,
+    value INTEGER NOT NULL,
+    PRIMARY KEY (flight_id, sample, channel)
+) WITHOUT ROWID;
+";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ImportStats {
+    pub flights: usize,
+    pub duplicates: usize,
+}
+
+pub fn open(path: &str) -> Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    conn.execute_batch(SCHEMA)?;
+    Ok(conn)
+}
+
+// imports one download. flights already in the database for the same tail, flight number and
+// start time are skipped, the EDM hands back its whole memory on every download
+pub fn import(conn: &mut Connection, headers: &[HeaderRecord], config: &ConfigInfo, flights: &[Flight]) -> Result<ImportStats> {
+    let tx = conn.transaction()?;
+
+    let tail = tail_number(headers).unwrap_or("");
+    tx.execute("INSERT OR IGNORE INTO aircraft (tail_number) VALUES (?1)", params![tail])?;
+    let aircraft_id: i64 = tx.query_row("SELECT id FROM aircraft WHERE tail_number = ?1", params![tail], |r| r.get(0))?;
+
+    let downloaded_at = headers.iter().find_map(|h| match h.record() {
+        HeaderRecord::T(t) => Some(t.datetime().unix_seconds()),
+        _ => None
+    }).unwrap_or(0);
+    tx.execute(
+        "INSERT OR IGNORE INTO downloads (aircraft_id, downloaded_at, model_number, feature_flags, unknown_flags, firmware_version)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![aircraft_id, downloaded_at, config.model_number, config_flags(config), config.unknown_flags, config.firmware_version],
+    )?;
+    let download_id: i64 = tx.query_row(
+        "SELECT id FROM downloads WHERE aircraft_id = ?1 AND downloaded_at = ?2",
+        params![aircraft_id, downloaded_at], |r| r.get(0))?;
+
+    let engines = num_engines(config);
+    let mut stats = ImportStats::default();
+    for flight in flights {
+        let summary = summarize(flight, engines, None);
+        let header = flight.header;
+        let start_time = summary.start.unix_seconds();
+
+        let existing: Option<i64> = tx.query_row(
+            "SELECT id FROM flights WHERE aircraft_id = ?1 AND flight_number = ?2 AND start_time = ?3",
+            params![aircraft_id, summary.flight_number, start_time], |r| r.get(0)).optional()?;
+        if existing.is_some() {
+            stats.duplicates += 1;
+            continue;
+        }
+
+        tx.execute(
+            "INSERT INTO flights (aircraft_id, download_id, flight_number, flags, unknown, interval_secs, start_time, duration_secs, samples, max_dif, max_rdif)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![aircraft_id, download_id, summary.flight_number, { header.flags }, { header.unknown }, { header.interval_secs },
+                    start_time, summary.duration_secs, summary.samples as i64, summary.max_dif[0], summary.max_dif[1]],
+        )?;
+        let flight_id = tx.last_insert_rowid();
+
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO flight_channels (flight_id, channel, min, max, mean, samples) VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            for c in &summary.channels {
+                insert.execute(params![flight_id, CHANNEL_NAMES[c.channel], c.min, c.max, c.mean, c.samples as i64])?;
+            }
+
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO samples (flight_id, sample, elapsed_secs, channel, value) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for (i, (record, elapsed)) in flight.records.iter().zip(flight.elapsed_secs()).enumerate() {
+                let values = record.data.values();
+                for c in &summary.channels {
+                    if record.available(c.channel) {
+                        insert.execute(params![flight_id, i as i64, elapsed, CHANNEL_NAMES[c.channel], values[c.channel]])?;
+                    }
+                }
+            }
+        }
+        stats.flights += 1;
+    }
+
+    tx.commit()?;
+    Ok(stats)
+}
+
+#[test]
+fn test_import() {
+    use crate::file::{test_download, test_flight, JpiFile, TEST_RECORDS};
+
+    let mut conn = open(":memory:").unwrap();
+    let tables: Vec<String> = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name").unwrap()
+        .query_map([], |r| r.get(0)).unwrap().collect::<Result<_>>().unwrap();
+    assert_eq!(tables, ["aircraft", "downloads", "flight_channels", "flights", "samples"]);
+
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS),
+                                                     test_flight(2, 0x1831F8FD, &TEST_RECORDS)])).unwrap();
+    let flights = file.decode_all().unwrap();
+    assert_eq!(import(&mut conn, file.headers(), file.config(), &flights).unwrap(), ImportStats { flights: 2, duplicates: 0 });
+    // downloading again hands back the same flights
+    assert_eq!(import(&mut conn, file.headers(), file.config(), &flights).unwrap(), ImportStats { flights: 0, duplicates: 2 });
+
+    let count = |table: &str| conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |r| r.get::<_, i64>(0)).unwrap();
+    assert_eq!((count("aircraft"), count("downloads"), count("flights")), (1, 1, 2));
+    let tail: String = conn.query_row("SELECT tail_number FROM aircraft", [], |r| r.get(0)).unwrap();
+    assert_eq!(tail, "N51SW");
+    // a record and two repeats of it in each flight
+    let e1: i64 = conn.query_row("SELECT count(*) FROM samples WHERE channel = 'E1'", [], |r| r.get(0)).unwrap();
+    assert_eq!(e1, 6);
+}
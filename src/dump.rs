@@ -0,0 +1,148 @@
+// an annotated hex listing of a download for working out the parts of the format nobody
+// understands yet. everything is shown as it sits in the file. a bad checksum is pointed out,
+// and the listing of a flight stops at the first record that can't be decoded
+
+use std::io::{self, Write};
+use std::mem::size_of;
+
+use crate::data::{binary_record, flightheader, parse_binary_record, record_layout, CHANNEL_NAMES, MIN_RECORD_LEN};
+use crate::file::JpiFile;
+use crate::headers::read_header_records;
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ")
+}
+
+fn checksum_note(stored: u8, calculated: u8) -> String {
+    if stored == calculated {
+        format!("checksum {:02X} ok", stored)
+    } else {
+        format!("checksum {:02X} BAD, calculated {:02X}", stored, calculated)
+    }
+}
+
+// the header lines and then every record of the flights at `indices` in the $D directory
+pub fn dump<W: Write>(out: &mut W, file: &JpiFile, indices: &[usize]) -> io::Result<()> {
+    let (records, data_start) = read_header_records(&mut file.data())?;
+    let mut offset = 0;
+    let text = &file.data()[..data_start as usize];
+    for (line, record) in text.split_inclusive(|&b| b == b'\n').zip(records.iter()) {
+        writeln!(out, "{:06X}  {:<40} {:?}", offset, String::from_utf8_lossy(line).trim_end(), record)?;
+        offset += line.len();
+    }
+
+    for &index in indices {
+        dump_flight(out, file, index)?;
+    }
+    Ok(())
+}
+
+fn dump_flight<W: Write>(out: &mut W, file: &JpiFile, index: usize) -> io::Result<()> {
+    let info = file.flights().nth(index)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no flight at index {}", index)))?;
+    let start = file.flight_range(index).start;
+    let data = file.flight_data(index);
+    writeln!(out, "\nflight {} at {:06X}, {} words", info.flight_number, start, info.length)?;
+
+    const HEADER_LEN: usize = size_of::<flightheader>();
+    let checksum = file.protocol().checksum;
+    if data.len() <= HEADER_LEN {
+        return writeln!(out, "{:06X}  {}  flight header cut short", start, hex(data));
+    }
+    let word = |i: usize| u16::from_be_bytes([data[i * 2], data[i * 2 + 1]]);
+    let header = flightheader {
+        flightnumber: word(0),
+        flags: (word(2) as u32) << 16 | word(1) as u32,
+        unknown: word(3),
+        interval_secs: word(4),
+        datebits: word(5),
+        timebits: word(6),
+    };
+    writeln!(out, "{:06X}  {}  flight header", start, hex(&data[..=HEADER_LEN]))?;
+    writeln!(out, "        number {} flags {:08X} unknown {:04X} interval {}s date {:04X} time {:04X} ({}), {}",
+             { header.flightnumber }, { header.flags }, { header.unknown }, { header.interval_secs },
+             { header.datebits }, { header.timebits }, header.start(),
+             checksum_note(data[HEADER_LEN], checksum.calc(&data[..HEADER_LEN])))?;
+
+    let config = file.config();
+    let mut prev = binary_record::new(config);
+    let mut i = &data[HEADER_LEN + 1..];
+    let mut sample = 0;
+    while i.len() >= MIN_RECORD_LEN {
+        let at = start + data.len() - i.len();
+        let (rest, layout) = match record_layout(i, checksum) {
+            Ok(layout) => layout,
+            Err(_) => return writeln!(out, "{:06X}  {}  not a record, stopping", at, hex(&i[..i.len().min(16)]))
+        };
+        writeln!(out, "{:06X}  {}", at, hex(&i[..i.len() - rest.len()]))?;
+
+        if layout.repeat != 0 {
+            let samples = match layout.repeat {
+                1 => format!("sample {}", sample),
+                n => format!("samples {}-{}", sample, sample + n as usize - 1),
+            };
+            writeln!(out, "        {}: decode {} repeat {}, the previous record again", samples, hex(&layout.decode_flags), layout.repeat)?;
+            sample += layout.repeat as usize;
+            i = rest;
+            continue;
+        }
+
+        writeln!(out, "        sample {}: decode {} repeat 0, field {} scale {} sign {}", sample, hex(&layout.decode_flags),
+                 hex(&layout.field_flags), hex(&layout.scale_flags), hex(&layout.sign_flags))?;
+        let decoded = parse_binary_record(&prev, i, config, &header, checksum).ok().map(|(_, r)| r);
+        for &(channel, delta) in &layout.field_deltas {
+            match (delta, decoded) {
+                (0, _) => writeln!(out, "        {:>8} n/a", CHANNEL_NAMES[channel])?,
+                (_, Some(record)) => writeln!(out, "        {:>8} {:+5} -> {}", CHANNEL_NAMES[channel], delta, record.data.values()[channel])?,
+                (_, None) => writeln!(out, "        {:>8} {:+5}", CHANNEL_NAMES[channel], delta)?,
+            }
+        }
+        for &(channel, delta) in &layout.scale_deltas {
+            writeln!(out, "        {:>8} {:+5} high byte", CHANNEL_NAMES[channel], delta)?;
+        }
+        if let Some((stored, calculated)) = layout.checksum {
+            writeln!(out, "        {}", checksum_note(stored, calculated))?;
+        }
+
+        // later records are deltas on this one, so past a bad record nothing means anything
+        match decoded {
+            Some(record) => prev = record,
+            None => return writeln!(out, "        can't decode this record, stopping"),
+        }
+        sample += 1;
+        i = rest;
+    }
+
+    if !i.is_empty() {
+        writeln!(out, "{:06X}  {}  padding", start + data.len() - i.len(), hex(i))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_dump() {
+    use crate::file::{test_download, test_flight, TEST_RECORDS};
+
+    let data = test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS), test_flight(2, 0x1831F8FD, &[])]);
+    let file = JpiFile::from_bytes(data).unwrap();
+    let mut out = Vec::new();
+    dump(&mut out, &file, &[0, 1]).unwrap();
+    let text = String::from_utf8(out).unwrap();
+    assert!(text.starts_with("000000  $U,N51SW"));
+    assert!(text.contains("\nflight 1 at "));
+    assert!(text.contains("\nflight 2 at "));
+    assert!(!text.contains("BAD"));
+    assert!(text.contains("E1   +10 -> 250"));
+    assert!(text.contains("checksum F3 ok"));
+    assert!(text.contains("samples 1-2: decode"));
+
+    // a corrupted record gets its checksum pointed out
+    let mut data = file.data().to_vec();
+    data[file.flight_range(0).start + 20] ^= 1; // the E1 delta
+    let mut out = Vec::new();
+    dump(&mut out, &JpiFile::from_bytes(data).unwrap(), &[0]).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("checksum F3 BAD"));
+
+    let error = dump(&mut Vec::new(), &file, &[2]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,33 @@
+use std::io::{self, Write};
+
+use crate::analysis::DerivedChannel;
+use crate::data::{binary_record, CHANNEL_NAMES};
+
+// derived channels go after RDIF, in the order given
+pub fn write_csv_header<W: Write>(out: &mut W, derived: &[DerivedChannel]) -> io::Result<()> {
+    write!(out, "FLIGHT,SAMPLE")?;
+    for name in CHANNEL_NAMES.iter() {
+        write!(out, ",{}", name)?;
+    }
+    write!(out, ",DIF,RDIF")?;
+    for channel in derived {
+        write!(out, ",{}", channel.name)?;
+    }
+    writeln!(out)
+}
+
+pub fn write_csv_record<W: Write>(out: &mut W, flight_number: u16, sample: usize, record: &binary_record,
+                                  derived: &[DerivedChannel]) -> io::Result<()> {
+    write!(out, "{},{}", flight_number, sample)?;
+    for value in record.data.values().iter() {
+        write!(out, ",{}", value)?;
+    }
+    write!(out, ",{},{}", record.dif[0], record.dif[1])?;
+    for channel in derived {
+        match channel.values.get(sample).copied().flatten() {
+            Some(value) => write!(out, ",{:.1}", value)?,
+            None => write!(out, ",")?
+        }
+    }
+    writeln!(out)
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::Path;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::data::{binary_record, flightheader, FlagDifference, io_error, parse_binary_record, read_flight_header,
+                  record_repeats, MIN_RECORD_LEN};
+use crate::headers::{read_header_records, ConfigInfo, FlightInfo, HeaderRecord, Protocol};
+
+// one decoded flight
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flight {
+    pub info: FlightInfo,
+    pub header: flightheader,
+    pub flag_difference: FlagDifference, // against $C, the flight's own flags are what got decoded
+    pub records: Vec<binary_record>,
+}
+
+impl Flight {
+    // seconds from the start of the flight to each record
+    pub fn elapsed_secs(&self) -> Vec<u32> {
+        let interval = self.header.interval_secs as u32;
+        (0..self.records.len() as u32).map(|i| i * interval).collect()
+    }
+}
+
+enum Bytes {
+    Owned(Vec<u8>),
+    #[cfg(feature = "mmap")]
+    Mapped(memmap2::Mmap),
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Bytes::Owned(v) => v,
+            #[cfg(feature = "mmap")]
+            Bytes::Mapped(m) => m,
+        }
+    }
+}
+
+// a whole JPI download held in (or mapped into) memory. flights are located up front from the
+// $D directory so each one can be decoded straight out of its own slice of the file
+pub struct JpiFile {
+    data: Bytes,
+    headers: Vec<HeaderRecord>,
+    config: ConfigInfo,
+    protocol: Protocol,
+    flights: Vec<(FlightInfo, Range<usize>)>,
+}
+
+impl JpiFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<JpiFile> {
+        JpiFile::from_bytes(fs::read(path)?)
+    }
+
+    // maps the file instead of reading it, the pages are only faulted in as flights get decoded
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> io::Result<JpiFile> {
+        let file = fs::File::open(path)?;
+        // the mapping is read only, truncating the file underneath us is on the caller
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        JpiFile::new(Bytes::Mapped(map))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> io::Result<JpiFile> {
+        JpiFile::new(Bytes::Owned(data))
+    }
+
+    fn new(data: Bytes) -> io::Result<JpiFile> {
+        let (headers, data_start) = read_header_records(&mut &data[..])?;
+
+        let config = headers.iter().find_map(|h| match h.record() {
+            HeaderRecord::C(cfg) => Some(*cfg),
+            _ => None
+        }).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing $C record"))?;
+        let protocol = Protocol::detect(&headers, &config, data.get(data_start as usize..).unwrap_or(&[]));
+
+        let mut offset = data_start as usize;
+        let flights = headers.iter().filter_map(|h| match h.record() {
+            HeaderRecord::D(info) => {
+                let start = offset;
+                offset += info.length as usize * 2;
+                Some((*info, start..offset))
+            }
+            _ => None
+        }).collect();
+
+        Ok(JpiFile {
+            data,
+            headers,
+            config,
+            protocol,
+            flights
+        })
+    }
+
+    pub fn headers(&self) -> &[HeaderRecord] {
+        &self.headers
+    }
+
+    pub fn config(&self) -> &ConfigInfo {
+        &self.config
+    }
+
+    pub fn protocol(&self) -> &Protocol {
+        &self.protocol
+    }
+
+    // the $D directory, in the order the flights appear in the file
+    pub fn flights(&self) -> impl Iterator<Item = &FlightInfo> {
+        self.flights.iter().map(|(info, _)| info)
+    }
+
+    // where each flight's bytes are in data()
+    pub fn flight_range(&self, index: usize) -> Range<usize> {
+        self.flights[index].1.clone()
+    }
+
+    // the whole download, headers included
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // the raw bytes of a flight, starting at its flight header. cut short if the file is truncated
+    pub fn flight_data(&self, index: usize) -> &[u8] {
+        let range = &self.flights[index].1;
+        let end = range.end.min(self.data.len());
+        &self.data[range.start.min(end)..end]
+    }
+
+    pub fn flight_header(&self, index: usize) -> io::Result<flightheader> {
+        read_flight_header(&mut self.flight_data(index), self.protocol.checksum)
+    }
+
+    pub fn decode_flight(&self, index: usize) -> io::Result<Flight> {
+        let mut i = self.flight_data(index);
+        let header = read_flight_header(&mut i, self.protocol.checksum)?;
+
+        let mut prev = binary_record::new(&self.config);
+        let mut records = Vec::new();
+        while i.len() >= MIN_RECORD_LEN {
+            let (rest, record) = parse_binary_record(&prev, i, &self.config, &header, self.protocol.checksum).map_err(io_error)?;
+            records.extend(std::iter::repeat_n(record, record_repeats(i)));
+            prev = record;
+            i = rest;
+        }
+
+        Ok(Flight {
+            info: self.flights[index].0,
+            header,
+            flag_difference: FlagDifference::new(&self.config, &header),
+            records
+        })
+    }
+
+    pub fn decode_all(&self) -> io::Result<Vec<Flight>> {
+        (0..self.flights.len()).map(|i| self.decode_flight(i)).collect()
+    }
+
+    // every flight starts over from binary_record::new so they can be decoded independently,
+    // the result is still in file order
+    #[cfg(feature = "parallel")]
+    pub fn decode_all_par(&self) -> io::Result<Vec<Flight>> {
+        (0..self.flights.len()).into_par_iter().map(|i| self.decode_flight(i)).collect()
+    }
+}
+
+// the bytes of a flight from its data records, which get their checksums added. a repeat record
+// is only its data header and has none
+#[cfg(test)]
+pub(crate) fn test_flight(flightnumber: u16, flags: u32, records: &[&[u8]]) -> Vec<u8> {
+    use crate::data::{write_flight_header, Checksum};
+
+    let mut header = flightheader { flightnumber, flags, interval_secs: 6, ..Default::default() };
+    header.set_start(crate::data::DateTime { year: 2005, month: 5, day: 13, hour: 22, ..Default::default() });
+    let mut data = write_flight_header(&header, Checksum::NegatedSum).to_vec();
+    for record in records {
+        data.extend_from_slice(record);
+        if record[2] == 0 {
+            data.push(Checksum::NegatedSum.calc(record));
+        }
+    }
+    data
+}
+
+// the $C of test_download
+#[cfg(test)]
+pub(crate) const TEST_CONFIG: ConfigInfo = ConfigInfo {
+    model_number: 700, feature_flags_lo: 63741, feature_flags_hi: 6193, unknown_flags: 1552, firmware_version: 292
+};
+
+// flight 1 made straight from decoded records, with the flags of TEST_CONFIG and a sample every 6 seconds
+#[cfg(test)]
+pub(crate) fn test_decoded_flight(records: Vec<binary_record>) -> Flight {
+    Flight {
+        info: FlightInfo { flight_number: 1, length: 0 },
+        header: flightheader { flightnumber: 1, flags: 0x1831F8FD, interval_secs: 6, ..Default::default() },
+        flag_difference: Default::default(),
+        records,
+    }
+}
+
+// a download of `flights`, numbered from 1 in the $D directory
+#[cfg(test)]
+pub(crate) fn test_download(flights: &[Vec<u8>]) -> Vec<u8> {
+    let config = TEST_CONFIG;
+    let timestamp = crate::headers::Timestamp { month: 5, day: 13, year: 5, hour: 23, minute: 2, unknown: 2222 };
+    let headers = [HeaderRecord::U { tail: "N51SW".to_owned(), padding: "__".to_owned() }, HeaderRecord::T(timestamp), HeaderRecord::C(config),
+                   HeaderRecord::L(Default::default())];
+    let flights = flights.iter().enumerate().map(|(i, data)| (i as u16 + 1, data.as_slice())).collect::<Vec<_>>();
+
+    let mut bytes = Vec::new();
+    crate::writer::write_jpi(&mut bytes, &headers, &flights).unwrap();
+    bytes
+}
+
+// E1 up by 10 and sampled twice more
+#[cfg(test)]
+pub(crate) const TEST_RECORDS: [&[u8]; 2] = [&[1, 1, 0, 0b1, 0, 10], &[0, 0, 2]];
+
+#[test]
+fn test_jpi_file() {
+    let bytes = test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS), test_flight(2, 0x1831F8FD, &TEST_RECORDS)]);
+    let flights = JpiFile::from_bytes(bytes.clone()).unwrap().decode_all().unwrap();
+    assert_eq!(flights.len(), 2);
+    assert_eq!(flights[1].info.flight_number, 2);
+    assert_eq!(flights[0].records.len(), 3);
+    assert_eq!(flights[0].records[0].data.egt[0], 0xF0 + 10);
+
+    #[cfg(feature = "parallel")]
+    assert_eq!(JpiFile::from_bytes(bytes.clone()).unwrap().decode_all_par().unwrap(), flights);
+
+    let path = std::env::temp_dir().join(format!("jpi-test-{}.JPI", std::process::id()));
+    fs::write(&path, &bytes).unwrap();
+    let read = JpiFile::open(&path).and_then(|f| f.decode_all());
+    #[cfg(feature = "mmap")]
+    let mapped = JpiFile::open_mmap(&path).and_then(|f| f.decode_all());
+    fs::remove_file(&path).unwrap();
+    assert_eq!(read.unwrap(), flights);
+    #[cfg(feature = "mmap")]
+    assert_eq!(mapped.unwrap(), flights);
+}
+
+#[test]
+fn test_flag_difference() {
+    use crate::headers::{FLAG_OAT, FLAG_RPM};
+
+    // the second flight had an RPM probe that $C doesn't list and had lost OAT
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS),
+                                                     test_flight(2, (0x1831F8FD | FLAG_RPM) & !FLAG_OAT, &TEST_RECORDS)])).unwrap();
+    let flights = file.decode_all().unwrap();
+    assert!(flights[0].flag_difference.is_empty());
+    assert_eq!(flights[1].flag_difference, FlagDifference { added: FLAG_RPM, removed: FLAG_OAT });
+    assert_eq!({ flights[1].header.flags } & FLAG_RPM, FLAG_RPM);
+}
@@ -2,11 +2,16 @@ use nom::{IResult, Parser};
 use nom::branch::alt;
 use nom::bytes::complete::{is_not, tag, take, take_until};
 use nom::character::complete::{space0, anychar};
-use nom::combinator::{eof, map_res, all_consuming};
+use nom::combinator::{eof, map_res, all_consuming, rest};
 use nom::error::{ErrorKind};
 use nom::sequence::{pair, delimited};
 use nom::character::complete;
 use std::ops::BitXor;
+use std::io::{self, BufRead};
+use std::fmt;
+use std::mem::size_of;
+
+use crate::data::{flightheader, Checksum, DateTime};
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct ConfiguredLimits {
@@ -22,13 +27,21 @@ pub struct ConfiguredLimits {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum HeaderRecord {
-    U(String),
+    // the tail number and whatever follows it, usually underscores padding it out
+    U { tail: String, padding: String },
     A(ConfiguredLimits),
     F(FuelFlowLimits),
     T(Timestamp),
     C(ConfigInfo),
     D(FlightInfo),
-    L(LastHeaderRecord)
+    L(LastHeaderRecord),
+    P(ProtocolInfo),
+    // a record this crate doesn't know, kept as the fields between the first , and the * so it
+    // can be written back out unchanged
+    Unknown { kind: char, fields: Vec<String> },
+    // a known record with more fields than it's known to have, from newer firmware. look at it
+    // through record()
+    Extended { record: Box<HeaderRecord>, extra: Vec<String> },
 }
 
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
@@ -50,6 +63,20 @@ pub struct Timestamp {
     pub unknown: u16,
 }
 
+impl Timestamp {
+    // the year is two digits
+    pub fn datetime(&self) -> DateTime {
+        DateTime {
+            year: 2000 + self.year,
+            month: self.month,
+            day: self.day,
+            hour: self.hour,
+            minute: self.minute,
+            second: 0,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, Debug, PartialEq)]
 pub struct ConfigInfo {
     pub model_number: u16,
@@ -70,6 +97,67 @@ pub struct LastHeaderRecord {
     pub unknown: u16
 }
 
+// written by later firmware with a data protocol version. what the versions mean isn't known,
+// see Protocol
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct ProtocolInfo {
+    pub version: u16
+}
+
+impl HeaderRecord {
+    // the letter after the $
+    pub fn kind(&self) -> char {
+        use HeaderRecord::*;
+        match self {
+            U { .. } => 'U',
+            A(_) => 'A',
+            F(_) => 'F',
+            T(_) => 'T',
+            C(_) => 'C',
+            D(_) => 'D',
+            L(_) => 'L',
+            P(_) => 'P',
+            Unknown { kind, .. } => *kind,
+            Extended { record, .. } => record.kind(),
+        }
+    }
+
+    // the known record, with any fields it was extended by left off
+    pub fn record(&self) -> &HeaderRecord {
+        match self {
+            HeaderRecord::Extended { record, .. } => record,
+            record => record,
+        }
+    }
+
+    // everything between the $ and the *
+    fn body(&self) -> String {
+        use HeaderRecord::*;
+        match self {
+            U { tail, padding } => format!("U,{}{}", tail, padding),
+            A(a) => format!("A,{:3},{:3},{:3},{:3},{:3},{:4},{:3},{:3}",
+                            a.volts_hi_times_ten, a.volts_lo_times_ten, a.dif, a.cht, a.cld, a.tit, a.oil_hi, a.oil_lo),
+            F(ff) => format!("F,{},{:3},{:3},{:4},{:4}", ff.empty, ff.full, ff.warning, ff.k_factor, ff.k_factor2),
+            T(t) => format!("T,{:2},{:2},{:2},{:2},{:2},{:5}", t.month, t.day, t.year, t.hour, t.minute, t.unknown),
+            C(c) => format!("C,{:4},{:5},{:5},{:5},{:4}",
+                            c.model_number, c.feature_flags_lo, c.feature_flags_hi, c.unknown_flags, c.firmware_version),
+            D(d) => format!("D,{:5},{:5}", d.flight_number, d.length),
+            L(l) => format!("L,{:3}", l.unknown),
+            P(p) => format!("P,{:2}", p.version),
+            Unknown { kind, fields } => format!("{},{}", kind, fields.join(",")),
+            Extended { record, extra } => format!("{},{}", record.body(), extra.join(",")),
+        }
+    }
+}
+
+// field widths follow what the EDM writes so rewritten headers look like the originals
+impl fmt::Display for HeaderRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let body = self.body();
+        write!(f, "${}*{:02X}", body, body.bytes().fold(0u8, u8::bitxor))
+    }
+}
+
 fn not_underscore(i: &str) -> nom::IResult<&str, &str> {
     is_not("_")(i)
 }
@@ -180,6 +268,14 @@ pub fn last_header_record_parser(i: &str) -> IResult<&str, LastHeaderRecord> {
     }))
 }
 
+pub fn protocol_info_parser(i: &str) -> IResult<&str, ProtocolInfo> {
+    let (i, version) = parse_short(i)?;
+
+    Ok((i, ProtocolInfo {
+        version,
+    }))
+}
+
 pub fn header_record_parser(line: &str) -> IResult<&str, (char, &str)> {
     let (i, _) = tag("$")(line)?;
     let (i, middle) = take_until("*")(i)?;
@@ -201,16 +297,169 @@ pub fn parse_header_record(i: &str) -> IResult<&str, HeaderRecord> {
     let (_, (record_type, data)) = all_consuming(header_record_parser)(i)?;
 
     use HeaderRecord::*;
-    match record_type {
-        'U' => tail_number_parser.map(|x| U(x.to_owned())).parse(data),
-        'A' => configured_limits_parser.map(|x| A(x)).parse(data),
-        'F' => fuel_flow_parser.map(|x| F(x)).parse(data),
-        'T' => timestamp_parser.map(|x| T(x)).parse(data),
-        'C' => config_info_parser.map(|x| C(x)).parse(data),
-        'D' => flight_info_parser.map(|x| D(x)).parse(data),
-        'L' => last_header_record_parser.map(|x| L(x)).parse(data),
-        _ => Err(nom::Err::Failure(nom::error::Error::new(i, ErrorKind::NoneOf)))
+    let (extra, record) = match record_type {
+        'U' => pair(tail_number_parser, rest).map(|(tail, padding)| U { tail: tail.to_owned(), padding: padding.to_owned() }).parse(data),
+        'A' => configured_limits_parser.map(A).parse(data),
+        'F' => fuel_flow_parser.map(F).parse(data),
+        'T' => timestamp_parser.map(T).parse(data),
+        'C' => config_info_parser.map(C).parse(data),
+        'D' => flight_info_parser.map(D).parse(data),
+        'L' => last_header_record_parser.map(L).parse(data),
+        'P' => protocol_info_parser.map(P).parse(data),
+        kind => return Ok(("", Unknown { kind, fields: data.split(',').map(str::to_owned).collect() })),
+    }?;
+    if extra.is_empty() {
+        return Ok(("", record));
+    }
+
+    // fields past the ones we know are kept so the record is written back whole
+    let extra = extra.strip_prefix(',').unwrap_or(extra).split(',').map(str::to_owned).collect();
+    Ok(("", Extended { record: Box::new(record), extra }))
+}
+
+// reads header records up to and including $L, returns them along with the number of bytes consumed.
+// a header without a $L ends where the lines stop starting with $, which validate reports
+pub fn read_header_records<R: BufRead>(reader: &mut R) -> io::Result<(Vec<HeaderRecord>, u64)> {
+    let mut records = Vec::new();
+    let mut consumed = 0u64;
+    let mut line = Vec::new();
+
+    loop {
+        // without a $L the flight data (or the end of the file) runs straight on from the last
+        // header record, so look before taking the line
+        if !records.is_empty() && reader.fill_buf()?.first() != Some(&b'$') {
+            return Ok((records, consumed));
+        }
+
+        line.clear();
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no header records"));
+        }
+        consumed += n as u64;
+
+        let text = std::str::from_utf8(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .trim_end_matches(&['\r', '\n'][..]);
+        let (_, record) = parse_header_record(text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+        let last = matches!(record.record(), HeaderRecord::L(_));
+        records.push(record);
+        if last {
+            return Ok((records, consumed));
+        }
+    }
+}
+
+// where the checksum changed, in $C's hundredths: XOR before 1.00, a plain byte sum up to 2.00,
+// the negated sum since. a firmware version of 0 means the unit didn't say
+pub const SUM_FIRMWARE: u16 = 100;
+pub const NEGATED_SUM_FIRMWARE: u16 = 200;
+
+// which form a download's flight data is in. every download seen so far, whatever its $P says,
+// has records laid out the way data.rs reads them, so all this picks is the checksum
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Protocol {
+    pub version: Option<u16>, // from $P, None for firmware that doesn't write one
+    pub checksum: Checksum,
+}
+
+impl Protocol {
+    // the checksum firmware new enough to write $P uses, otherwise the one its version says. that's
+    // checked against `first_flight`, the bytes from the start of the first flight header, and if
+    // they don't check out with it but do with another checksum, the data wins
+    pub fn detect(records: &[HeaderRecord], config: &ConfigInfo, first_flight: &[u8]) -> Protocol {
+        let version = records.iter().find_map(|r| match r.record() {
+            HeaderRecord::P(p) => Some(p.version),
+            _ => None
+        });
+
+        let candidate = match (version, config.firmware_version) {
+            (Some(_), _) | (None, 0) => Checksum::NegatedSum,
+            (None, f) if f < SUM_FIRMWARE => Checksum::Xor,
+            (None, f) if f < NEGATED_SUM_FIRMWARE => Checksum::Sum,
+            (None, _) => Checksum::NegatedSum,
+        };
+        let checksum = Protocol::verify(candidate, first_flight.get(..size_of::<flightheader>() + 1));
+        Protocol { version, checksum }
     }
+
+    // `candidate`, unless the first flight header doesn't check out with it and another checksum does
+    fn verify(candidate: Checksum, header: Option<&[u8]>) -> Checksum {
+        match header.and_then(|h| h.split_last()) {
+            Some((stored, body)) if candidate.calc(body) != *stored => header.and_then(Checksum::detect).unwrap_or(candidate),
+            _ => candidate
+        }
+    }
+}
+
+pub fn tail_number(records: &[HeaderRecord]) -> Option<&str> {
+    records.iter().find_map(|r| match r.record() {
+        HeaderRecord::U { tail, .. } => Some(tail.as_str()),
+        _ => None
+    })
+}
+
+// feature flag bits, the same in $C and in each flight header. cylinders take bits 2-10 (see num_cyls),
+// the rest come from comparing downloads against known installs
+pub const FLAG_BAT: u32 = 1 << 0;
+pub const FLAG_OIL: u32 = 1 << 11;
+pub const FLAG_TIT: u32 = 1 << 12;
+pub const FLAG_TIT2: u32 = 1 << 13;
+pub const FLAG_OAT: u32 = 1 << 14;
+pub const FLAG_CLD: u32 = 1 << 16;
+pub const FLAG_CDT: u32 = 1 << 17;
+pub const FLAG_IAT: u32 = 1 << 18;
+pub const FLAG_MAP: u32 = 1 << 19;
+pub const FLAG_FF: u32 = 1 << 20;
+pub const FLAG_HP: u32 = 1 << 25;
+pub const FLAG_RPM: u32 = 1 << 26;
+
+// what the EDM says is installed, decoded from the feature flags in $C. the display unit and
+// whether the CDT slot holds carb temp aren't, as no flag for either is documented
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EdmConfiguration {
+    pub model_number: u16,
+    pub firmware_version: u16, // hundredths
+    pub engines: u32,
+    pub cylinders: u32,
+    pub tit_probes: u32, // 0, 1 or 2
+    pub oil: bool,
+    pub oat: bool,
+    pub iat: bool,
+    pub cdt: bool, // compressor discharge or carburetor temperature, the flags don't say which
+    pub rpm: bool,
+    pub map: bool,
+    pub ff: bool,
+    pub hp: bool,
+}
+
+impl EdmConfiguration {
+    pub fn new(config: &ConfigInfo) -> EdmConfiguration {
+        let flags = config_flags(config);
+        let has = |flag: u32| flags & flag == flag;
+
+        EdmConfiguration {
+            model_number: config.model_number,
+            firmware_version: config.firmware_version,
+            engines: num_engines(config),
+            cylinders: num_cyls(flags),
+            tit_probes: has(FLAG_TIT) as u32 + has(FLAG_TIT2) as u32,
+            oil: has(FLAG_OIL),
+            oat: has(FLAG_OAT),
+            iat: has(FLAG_IAT),
+            cdt: has(FLAG_CDT),
+            rpm: has(FLAG_RPM),
+            map: has(FLAG_MAP),
+            ff: has(FLAG_FF),
+            hp: has(FLAG_HP),
+        }
+    }
+}
+
+pub fn config_flags(config: &ConfigInfo) -> u32 {
+    (config.feature_flags_hi as u32) << 16 | (config.feature_flags_lo as u32)
 }
 
 pub fn num_engines(config: &ConfigInfo) -> u32 {
@@ -221,3 +470,121 @@ pub fn num_cyls(flags: u32) -> u32 {
     let mask = 0b11111111100;
     ((flags & mask) >> 2).trailing_ones()
 }
+
+#[test]
+fn test_display() {
+    for line in ["$U,N51SW__*37", "$U,N51SW*37", "$U,N12345_ABC_*46", "$A,155,130,400,415, 60,1650,220, 75*70", "$F,0, 49, 22,3183,3183*57",
+                 "$T, 5,13, 5,23, 2, 2222*65", "$C, 700,63741, 6193, 1552, 292*58", "$D,  227, 3979*57", "$L, 49*4D"].iter() {
+        assert_eq!(parse_header_record(line).unwrap().1.to_string(), *line);
+    }
+}
+
+#[test]
+fn test_unknown_record() {
+    let line = "$X,a, 1,,b*4A";
+    assert_eq!(parse_header_record(line), Ok(("", HeaderRecord::Unknown {
+        kind: 'X', fields: vec!["a".to_owned(), " 1".to_owned(), "".to_owned(), "b".to_owned()]
+    })));
+    assert_eq!(parse_header_record(line).unwrap().1.to_string(), line);
+    assert!(parse_header_record("$X,a, 1,,b*4B").is_err());
+}
+
+#[test]
+fn test_extended_record() {
+    use crate::file::{test_download, test_flight, JpiFile, TEST_RECORDS};
+
+    let line = "$C, 830,63741, 6193, 1552, 292, 7, 9*5A";
+    let config = ConfigInfo { model_number: 830, ..crate::file::TEST_CONFIG };
+    let (_, record) = parse_header_record(line).unwrap();
+    assert_eq!(record, HeaderRecord::Extended {
+        record: Box::new(HeaderRecord::C(config)), extra: vec![" 7".to_owned(), " 9".to_owned()]
+    });
+    assert_eq!(record.kind(), 'C');
+    assert_eq!(record.record(), &HeaderRecord::C(config));
+    assert_eq!(record.to_string(), line);
+
+    // and through a rewrite, along with an extended $D
+    let download = test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS)]);
+    let (_, data_start) = read_header_records(&mut &download[..]).unwrap();
+    let (text, flights) = download.split_at(data_start as usize);
+    let text = std::str::from_utf8(text).unwrap()
+        .replace("$C, 700,63741, 6193, 1552, 292*58", line)
+        .replace("$D,    1,   13*57", "$D,    1,   13, 5*6E");
+    let file = JpiFile::from_bytes([text.as_bytes(), flights].concat()).unwrap();
+    assert_eq!(file.config(), &config);
+    assert_eq!(file.headers().iter().filter(|h| matches!(h, HeaderRecord::Extended { .. })).count(), 2);
+    let mut bytes = Vec::new();
+    crate::writer::extract(&mut bytes, &file, &[0]).unwrap();
+    let extracted = JpiFile::from_bytes(bytes).unwrap();
+    assert_eq!(extracted.headers(), file.headers());
+    assert_eq!(extracted.decode_all().unwrap(), file.decode_all().unwrap());
+}
+
+#[test]
+fn test_protocol() {
+    let config = crate::file::TEST_CONFIG;
+    assert_eq!(parse_header_record("$P, 2*6E"), Ok(("", HeaderRecord::P(ProtocolInfo { version: 2 }))));
+    assert_eq!(parse_header_record("$P, 2*6E").unwrap().1.to_string(), "$P, 2*6E");
+
+    let old = ConfigInfo { firmware_version: 105, ..config };
+    let oldest = ConfigInfo { firmware_version: 95, ..config };
+    let unknown = ConfigInfo { firmware_version: 0, ..config };
+    let p = |version| [HeaderRecord::P(ProtocolInfo { version })];
+    assert_eq!(Protocol::detect(&[], &config, &[]).checksum, Checksum::NegatedSum);
+    assert_eq!(Protocol::detect(&[], &old, &[]), Protocol { version: None, checksum: Checksum::Sum });
+    assert_eq!(Protocol::detect(&[], &oldest, &[]).checksum, Checksum::Xor);
+    assert_eq!(Protocol::detect(&p(2), &old, &[]), Protocol { version: Some(2), checksum: Checksum::NegatedSum });
+    assert_eq!(Protocol::detect(&p(3), &old, &[]), Protocol { version: Some(3), checksum: Checksum::NegatedSum });
+
+    let mut flight = vec![0, 227, 0xF8, 0xFD, 0x18, 0x31, 0, 0, 0, 6, 0, 0, 0, 0];
+    for checksum in Checksum::ALL.iter() {
+        flight.push(checksum.calc(&flight));
+        assert_eq!(Protocol::detect(&[], &unknown, &flight).checksum, *checksum);
+        // $P is checked against the first flight header
+        assert_eq!(Protocol::detect(&p(2), &config, &flight).checksum, *checksum);
+        flight.pop();
+    }
+    // and kept when nothing checks out
+    flight.push(0);
+    assert_eq!(Protocol::detect(&p(2), &config, &flight).checksum, Checksum::NegatedSum);
+}
+
+#[test]
+fn test_edm_configuration() {
+    let config = crate::file::TEST_CONFIG;
+    let edm = EdmConfiguration::new(&config);
+    assert_eq!((edm.engines, edm.cylinders, edm.tit_probes), (1, 6, 2));
+    assert!(edm.oil && edm.oat && edm.ff && !edm.iat && !edm.cdt && !edm.rpm && !edm.map && !edm.hp);
+    let edm = EdmConfiguration::new(&ConfigInfo { feature_flags_hi: 6195, ..config });
+    assert!(edm.cdt && !edm.iat);
+}
+
+#[test]
+fn test_download_checksums() {
+    use crate::data::{write_flight_header, DateTime};
+    use crate::file::{JpiFile, TEST_RECORDS};
+
+    let download = |firmware_version, checksum: Checksum| {
+        let config = ConfigInfo { firmware_version, ..crate::file::TEST_CONFIG };
+        let mut header = flightheader { flightnumber: 1, flags: 0x1831F8FD, interval_secs: 6, ..Default::default() };
+        header.set_start(DateTime { year: 2005, month: 5, day: 13, hour: 22, ..Default::default() });
+        let mut flight = write_flight_header(&header, checksum).to_vec();
+        flight.extend_from_slice(TEST_RECORDS[0]);
+        flight.push(checksum.calc(TEST_RECORDS[0]));
+        flight.extend_from_slice(TEST_RECORDS[1]);
+
+        let mut bytes = Vec::new();
+        let headers = [HeaderRecord::U { tail: "N51SW".to_owned(), padding: "__".to_owned() }, HeaderRecord::C(config), HeaderRecord::L(Default::default())];
+        crate::writer::write_jpi(&mut bytes, &headers, &[(1, flight.as_slice())]).unwrap();
+        JpiFile::from_bytes(bytes).unwrap()
+    };
+
+    // XOR from firmware before 1.00, the plain sum up to 2.00, and data that disagrees with its firmware
+    for &(firmware_version, checksum) in &[(95, Checksum::Xor), (150, Checksum::Sum), (150, Checksum::Xor), (292, Checksum::Sum)] {
+        let file = download(firmware_version, checksum);
+        assert_eq!(file.protocol().checksum, checksum);
+        let flights = file.decode_all().unwrap();
+        assert_eq!(flights[0].records.len(), 3);
+        assert_eq!(flights[0].records[0].data.egt[0], 0xF0 + 10);
+    }
+}
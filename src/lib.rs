@@ -0,0 +1,21 @@
+#![allow(non_camel_case_types)] // the data structs keep the names from the reference decoder
+
+pub mod headers;
+pub mod data;
+pub mod reader;
+pub mod file;
+pub mod export;
+pub mod summary;
+pub mod writer;
+pub mod merge;
+pub mod anonymize;
+pub mod validate;
+pub mod plot;
+pub mod analysis;
+pub mod resample;
+pub mod dump;
+
+#[cfg(feature = "arrow")]
+pub mod columnar;
+#[cfg(feature = "sqlite")]
+pub mod db;
@@ -1,17 +1,95 @@
-mod headers;
-mod data;
-
-use headers::*;
-use data::*;
-use nom::error::ErrorKind;
+#[cfg(feature = "arrow")]
+use jpi_parser::columnar::{record_batch, write_parquet};
+#[cfg(feature = "sqlite")]
+use jpi_parser::db;
+use jpi_parser::export::{write_csv_header, write_csv_record};
+use jpi_parser::anonymize::{self, Anonymize, DateChange};
+use jpi_parser::validate::validate;
+use jpi_parser::analysis::alarms::{replay, AlarmSettings};
+use jpi_parser::analysis::cooling::shock_cooling;
+use jpi_parser::analysis::faults::sensor_faults;
+use jpi_parser::analysis::fuel::{corrected_k_factor, fuel_used};
+use jpi_parser::analysis::derived_channels;
+use jpi_parser::analysis::lean::lean_finds;
+use jpi_parser::analysis::phases::phase_reports;
+use jpi_parser::analysis::power::EngineModel;
+use jpi_parser::analysis::trend::{cruise_averages, drifting};
+use jpi_parser::analysis::Limit;
+use jpi_parser::data::{channel_scale, parse_channel_list, DateTime, CHANNEL_NAMES};
+use jpi_parser::resample::{channel_values, downsample, resample, Aggregate, Interpolation};
+use jpi_parser::{dump, merge, plot, writer};
+use jpi_parser::file::{Flight, JpiFile};
+use jpi_parser::headers::{num_engines, tail_number, ConfigInfo, EdmConfiguration, HeaderRecord};
+use jpi_parser::reader::JpiReader;
+use std::env;
 use std::fs::File;
-use std::io::{BufReader, Read, BufRead, Seek};
-use std::mem::size_of;
-use headers::HeaderRecord::*;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "usage: jpi <command> [options] FILE...
+
+commands:
+    print FILE|-        print every header record and decoded sample
+    info FILE|-...      show what the EDM has installed, to check it against the airplane
+    dump (--all | --flight N...) [--mmap] [-o OUT] FILE
+                        hex listing of the header lines and every record: offsets, decode, flag
+                        and sign bytes, the change applied to each channel, and checksums
+    export (--all | --flight N...) [--mmap] [--format csv|parquet] [--derived cht-rate,percent-power]
+           [--engine ENGINE] [-o OUT] FILE|-...
+                        write decoded samples as CSV, or Parquet when built with the arrow feature.
+                        --derived adds computed columns: cht-rate is each CHT's change in °/min,
+                        percent-power comes from HP or is estimated using the --engine model
+    resample (--all | --flight N...) --every SECS [--linear | --min | --max | --mean]
+             [--channels E1-E6,C1-C6] [-o OUT] FILE...
+                        write channels as CSV on a fixed time step, by nearest sample unless
+                        --linear interpolates or --min/--max/--mean combine each step's samples
+    merge [-o OUT.JPI] [--mmap] FILE...
+                        combine downloads of one aircraft, listing each flight once and flagging
+                        copies that disagree
+    verify [--mmap] FILE...
+                        check checksums and that header values make sense, exits 1 on any problem
+    plot --flight N [--channels E1-E6,C1-C6] -o OUT.svg|OUT.png FILE
+                        chart channels over a flight with the configured limits drawn in
+                        (png needs the png feature)
+    lean (--all | --flight N...) FILE...
+                        find lean-of-peak operations, each cylinder's peak EGT and the GAMI spread
+    phases (--all | --flight N...) [--engine ENGINE] FILE...
+                        split flights into taxi, runup, takeoff, climb, cruise and descent, with
+                        statistics, percent power and limit exceedances for each
+    trend [--last N] [--mmap] FILE...
+                        per-cylinder cruise EGT and CHT, oil and spreads over one aircraft's last
+                        N flights (default 20), flagging cylinders drifting from the rest
+    faults (--all | --flight N...) FILE...
+                        find probes that look broken: impossible readings or jumps, readings stuck
+                        while the others move, and channels that keep dropping out
+    fuel (--all | --flight N...) [--actual GALLONS] FILE
+                        compare fuel flow added up over each flight with what the totalizer
+                        counted. --actual is the fuel a fill-up took after the selected flights,
+                        and suggests the K-factor that would have matched it
+    alarms (--all | --flight N...) [--delay SECS] [--hysteresis DEGREES] FILE...
+                        replay flights against the configured limits the way the EDM alarms: a
+                        reading has to stay past its limit for --delay (10s) to alarm, and come
+                        back by --hysteresis (5°) to clear
+    cooling (--all | --flight N...) FILE...
+                        find cylinders cooling faster than the configured shock cooling limit
+    extract (--all | --flight N...) -o OUT.JPI [--mmap] FILE
+                        write a standalone download holding only the selected flights
+    anonymize -o OUT.JPI [--tail N00000] [--shift-days N | --strip-dates] FILE
+                        replace the tail number and move or remove dates, samples are untouched
+    import --db DB [--mmap] FILE|-...
+                        add downloads to a SQLite fleet database, skipping flights it already has
+                        (needs the sqlite feature)
+
+FILE can be - to read a download from stdin. ENGINE is a file of key = value lines giving
+rated_hp and optionally rated_rpm, rated_map (inHg), hp_per_gph (lean of peak) and
+rich_hp_per_gph";
 
 
 #[test]
  fn test() {
+     use jpi_parser::headers::*;
+     use nom::error::ErrorKind;
+
      assert_eq!(tail_number_parser("N51SW__"), Ok(("__", "N51SW")));
      assert_eq!(tail_number_parser("__N51SW"), Err(nom::Err::Error(nom::error::Error::new("__N51SW", ErrorKind::IsNot))));
 
@@ -73,54 +151,795 @@ use headers::HeaderRecord::*;
      assert_eq!(parse_header_record("$L, 49*4D"), Ok(("", HeaderRecord::L(last_header_record_example))));
  }
 
-fn main() {
-    let raw: &str = "$U,N51SW__*37";
-    //println!("{:?}", header_record_parser(raw));
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    // `takes_value` lists the options that consume the following argument
+    fn parse<I: Iterator<Item = String>>(args: I, takes_value: &[&str]) -> Args {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        let mut args = args;
+
+        while let Some(arg) = args.next() {
+            if arg.starts_with('-') && arg != "-" {
+                let value = if takes_value.contains(&arg.as_str()) {
+                    Some(args.next().unwrap_or_else(|| usage_error(&format!("{} needs a value", arg))))
+                } else {
+                    None
+                };
+                options.push((arg, value));
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        Args { positional, options }
+    }
+
+    fn has(&self, name: &str) -> bool {
+        self.options.iter().any(|(o, _)| o == name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(o, _)| o == name).and_then(|(_, v)| v.as_deref())
+    }
+
+    fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.options.iter().filter(move |(o, _)| o == name).filter_map(|(_, v)| v.as_deref())
+    }
+}
 
-    //println!("{:?}", configured_limits_parser("155,130,400,415, 60,1650,220, 75"));
+fn usage_error(message: &str) -> ! {
+    eprintln!("jpi: {}\n\n{}", message, USAGE);
+    process::exit(2)
+}
 
-    let f = File::open("U210818.JPI").unwrap();
-    let mut reader = BufReader::new(f);
+fn open_source(path: &str) -> io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        Ok(Box::new(BufReader::new(io::stdin())))
+    } else {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    }
+}
 
-    let mut header_records = Vec::new();
+fn open_file(path: &str, mmap: bool) -> io::Result<JpiFile> {
+    if mmap {
+        #[cfg(feature = "mmap")]
+        return JpiFile::open_mmap(path);
+        #[cfg(not(feature = "mmap"))]
+        usage_error("--mmap needs jpi to be built with the mmap feature");
+    }
+    JpiFile::open(path)
+}
 
-    for line in reader.by_ref().lines() {
-        let (_, record) = parse_header_record(line.unwrap().as_str()).unwrap();
-        let mut last = false;
-        if let L(_) = record {
-            last = true;
+fn print(args: &Args) -> io::Result<()> {
+    let path = args.positional.first().unwrap_or_else(|| usage_error("print needs a file"));
+    let mut reader = JpiReader::new(open_source(path)?)?;
+    for record in reader.headers() {
+        println!("{:?}", record);
+    }
+
+    while let Some(flight) = reader.next_flight() {
+        let (info, decoder) = flight?;
+        println!("{:?} {:?}", info, decoder.header());
+        if !decoder.flag_difference().is_empty() {
+            println!("{:?}", decoder.flag_difference());
         }
-        header_records.push(record);
-        if last {
-            break;
+        for record in decoder {
+            println!("{:?}", record?.data);
+        }
+    }
+
+    Ok(())
+}
+
+fn info(args: &Args) -> io::Result<()> {
+    if args.positional.is_empty() {
+        usage_error("info needs a file");
+    }
+
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    for path in &args.positional {
+        let reader = JpiReader::new(open_source(path)?)?;
+        let edm = EdmConfiguration::new(reader.config());
+        let engines = if edm.engines == 1 { "1 engine".to_owned() } else { format!("{} engines", edm.engines) };
+        println!("{}: {}, EDM {}, firmware {}.{:02}, {}", path, tail_number(reader.headers()).unwrap_or("no tail number"),
+                 edm.model_number, edm.firmware_version / 100, edm.firmware_version % 100, engines);
+        println!("    cylinders     {}", edm.cylinders);
+        println!("    TIT probes    {}", edm.tit_probes);
+        for (name, installed) in [("oil", edm.oil), ("OAT", edm.oat), ("IAT", edm.iat), ("CDT/CRB", edm.cdt),
+                                  ("RPM", edm.rpm), ("MAP", edm.map), ("FF", edm.ff), ("HP", edm.hp)] {
+            println!("    {:<13} {}", name, yes_no(installed));
+        }
+        // neither is decoded: no documented flag gives the display unit or tells carb temp from CDT
+        println!("    carb temp     {}", if edm.cdt { "unknown, CDT/CRB could be either" } else { "no" });
+        println!("    temperatures  unknown, °F or °C isn't decoded");
+    }
+    Ok(())
+}
+
+// which flights a command should look at, from --all or one or more --flight N
+struct Selection {
+    all: bool,
+    flights: Vec<u16>,
+}
+
+impl Selection {
+    fn from_args(args: &Args, command: &str) -> Selection {
+        let all = args.has("--all");
+        let flights = args.values("--flight")
+            .map(|f| f.parse::<u16>().unwrap_or_else(|_| usage_error(&format!("bad flight number {}", f))))
+            .collect::<Vec<_>>();
+        if all != flights.is_empty() {
+            usage_error(&format!("{} needs either --all or --flight", command));
+        }
+        Selection { all, flights }
+    }
+
+    fn wants(&self, flight_number: u16) -> bool {
+        self.all || self.flights.contains(&flight_number)
+    }
+
+    fn decode(&self, file: &JpiFile) -> io::Result<Vec<Flight>> {
+        if self.all {
+            #[cfg(feature = "parallel")]
+            return file.decode_all_par();
+            #[cfg(not(feature = "parallel"))]
+            return file.decode_all();
+        }
+
+        let indices = file.flights().enumerate()
+            .filter(|(_, info)| self.wants(info.flight_number))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        indices.into_iter().map(|i| file.decode_flight(i)).collect()
+    }
+}
+
+// headers and the selected flights of one input, stdin gets decoded a flight at a time
+fn load(path: &str, selection: &Selection, mmap: bool) -> io::Result<(Vec<HeaderRecord>, ConfigInfo, Vec<Flight>)> {
+    if path != "-" {
+        let file = open_file(path, mmap)?;
+        let flights = selection.decode(&file)?;
+        return Ok((file.headers().to_vec(), *file.config(), flights));
+    }
+
+    let mut reader = JpiReader::new(open_source(path)?)?;
+    let mut flights = Vec::new();
+    while let Some(flight) = reader.next_flight() {
+        let (info, decoder) = flight?;
+        if selection.wants(info.flight_number) {
+            let header = *decoder.header();
+            let flag_difference = decoder.flag_difference();
+            let records = decoder.collect::<io::Result<Vec<_>>>()?;
+            flights.push(Flight { info, header, flag_difference, records });
+        }
+    }
+    Ok((reader.headers().to_vec(), *reader.config(), flights))
+}
+
+fn open_output(args: &Args) -> io::Result<Box<dyn Write + Send>> {
+    match args.value("-o") {
+        Some(path) => Ok(Box::new(BufWriter::new(File::create(path)?))),
+        None => Ok(Box::new(BufWriter::new(io::stdout())))
+    }
+}
+
+fn export(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "export");
+    if args.positional.is_empty() {
+        usage_error("export needs a file");
+    }
+
+    match args.value("--format").unwrap_or("csv") {
+        "csv" => export_csv(args, &selection),
+        #[cfg(feature = "arrow")]
+        "parquet" => export_parquet(args, &selection),
+        format => usage_error(&format!("unknown export format {}", format))
+    }
+}
+
+fn export_csv(args: &Args, selection: &Selection) -> io::Result<()> {
+    let mut out = open_output(args)?;
+    let derived = derived_names(args);
+    let model = engine_model(args)?;
+    // the derived columns depend on the engine count, so the header waits for the first flight
+    let mut header_written = false;
+
+    for path in &args.positional {
+        if path == "-" && derived.is_empty() { // csv can go straight through without collecting the flight
+            let mut reader = JpiReader::new(open_source(path)?)?;
+            while let Some(flight) = reader.next_flight() {
+                let (info, decoder) = flight?;
+                if !selection.wants(info.flight_number) {
+                    continue;
+                }
+                if !header_written {
+                    write_csv_header(&mut out, &[])?;
+                    header_written = true;
+                }
+                for (i, record) in decoder.enumerate() {
+                    write_csv_record(&mut out, info.flight_number, i, &record?, &[])?;
+                }
+            }
+            continue;
+        }
+
+        let (_, config, flights) = load(path, selection, args.has("--mmap"))?;
+        for flight in &flights {
+            let channels = derived_channels(flight, num_engines(&config), &derived, model.as_ref())
+                .unwrap_or_else(|e| usage_error(&e));
+            if !header_written {
+                write_csv_header(&mut out, &channels)?;
+                header_written = true;
+            }
+            for (i, record) in flight.records.iter().enumerate() {
+                write_csv_record(&mut out, flight.info.flight_number, i, record, &channels)?;
+            }
+        }
+    }
+
+    if !header_written {
+        write_csv_header(&mut out, &[])?;
+    }
+    out.flush()
+}
+
+// the engine model from --engine FILE, for percent power where the EDM doesn't record HP
+fn engine_model(args: &Args) -> io::Result<Option<EngineModel>> {
+    match args.value("--engine") {
+        Some(path) => EngineModel::parse(&std::fs::read_to_string(path)?)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e))),
+        None => Ok(None)
+    }
+}
+
+// --derived can be repeated or given a comma separated list
+fn derived_names(args: &Args) -> Vec<&str> {
+    args.values("--derived").flat_map(|v| v.split(',')).filter(|n| !n.is_empty()).collect()
+}
+
+#[cfg(feature = "arrow")]
+fn export_parquet(args: &Args, selection: &Selection) -> io::Result<()> {
+    let mut batches = Vec::new();
+    let model = engine_model(args)?;
+    for path in &args.positional {
+        let (headers, config, flights) = load(path, selection, args.has("--mmap"))?;
+        let tail = tail_number(&headers).unwrap_or("");
+        let derived = flights.iter()
+            .map(|f| derived_channels(f, num_engines(&config), &derived_names(args), model.as_ref()))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| usage_error(&e));
+        batches.push(record_batch(&flights, &derived, &config, tail).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?);
+    }
+
+    write_parquet(open_output(args)?, &batches).map_err(io::Error::other)
+}
+
+fn resample_csv(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "resample");
+    let step = match args.value("--every").map(str::parse::<u32>) {
+        Some(Ok(n)) if n > 0 => n,
+        _ => usage_error("resample needs --every SECS")
+    };
+    let channels = match args.value("--channels") {
+        Some(list) => parse_channel_list(list).unwrap_or_else(|e| usage_error(&e)),
+        None => (0..CHANNEL_NAMES.len()).collect()
+    };
+    let aggregate = [("--min", Aggregate::Min), ("--max", Aggregate::Max), ("--mean", Aggregate::Mean)].iter()
+        .find(|(option, _)| args.has(option))
+        .map(|&(_, how)| how);
+    let interpolation = if args.has("--linear") { Interpolation::Linear } else { Interpolation::Nearest };
+
+    let mut out = open_output(args)?;
+    write!(out, "FLIGHT,SECONDS")?;
+    for &c in &channels {
+        write!(out, ",{}", CHANNEL_NAMES[c])?;
+    }
+    writeln!(out)?;
+
+    for path in &args.positional {
+        let (_, config, flights) = load(path, &selection, args.has("--mmap"))?;
+        for flight in &flights {
+            let elapsed = flight.elapsed_secs();
+            let columns = channels.iter().map(|&c| {
+                let values = channel_values(flight, num_engines(&config), c);
+                match aggregate {
+                    Some(how) => downsample(&elapsed, &values, step, how),
+                    None => resample(&elapsed, &values, step, interpolation)
+                }
+            }).collect::<Vec<_>>();
+
+            for row in 0..columns.first().map_or(0, Vec::len) {
+                write!(out, "{},{}", flight.info.flight_number, row as u32 * step)?;
+                for column in &columns {
+                    match column[row] {
+                        Some(value) => write!(out, ",{}", (value * 10.0).round() / 10.0)?,
+                        None => write!(out, ",")?
+                    }
+                }
+                writeln!(out)?;
+            }
+        }
+    }
+
+    out.flush()
+}
+
+fn dump_file(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "dump");
+    let path = match args.positional.as_slice() {
+        [path] => path,
+        _ => usage_error("dump needs one file")
+    };
+
+    let file = open_file(path, args.has("--mmap"))?;
+    let indices = file.flights().enumerate()
+        .filter(|(_, info)| selection.wants(info.flight_number))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let mut out = open_output(args)?;
+    dump::dump(&mut out, &file, &indices)?;
+    out.flush()
+}
+
+fn merge(args: &Args) -> io::Result<()> {
+    if args.positional.is_empty() {
+        usage_error("merge needs a file");
+    }
+
+    let files = args.positional.iter().map(|p| open_file(p, args.has("--mmap"))).collect::<io::Result<Vec<_>>>()?;
+    let merged = merge::merge(&files)?;
+
+    for flight in &merged.flights {
+        let copies = flight.sources.iter().map(|&s| args.positional[s].as_str()).collect::<Vec<_>>().join(", ");
+        print!("flight {} {}: {}", flight.flight_number(), flight.start(), copies);
+        if !flight.conflicts.is_empty() {
+            let others = flight.conflicts.iter().map(|&s| args.positional[s].as_str()).collect::<Vec<_>>().join(", ");
+            print!(" (CONFLICT: differs in {})", others);
+        }
+        println!();
+    }
+    println!("{} flights, {} with conflicting copies", merged.flights.len(), merged.conflicts().count());
+
+    if let Some(path) = args.value("-o") {
+        let mut out = BufWriter::new(File::create(path)?);
+        merged.write(&mut out)?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+fn extract(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "extract");
+    let (path, out) = match (args.positional.as_slice(), args.value("-o")) {
+        ([path], Some(out)) => (path, out),
+        _ => usage_error("extract needs one file and -o")
+    };
+
+    let file = open_file(path, args.has("--mmap"))?;
+    let indices = file.flights().enumerate()
+        .filter(|(_, info)| selection.wants(info.flight_number))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if indices.is_empty() {
+        usage_error(&format!("none of the selected flights are in {}", path));
+    }
+
+    let mut writer = BufWriter::new(File::create(out)?);
+    writer::extract(&mut writer, &file, &indices)?;
+    writer.flush()
+}
+
+fn anonymize(args: &Args) -> io::Result<()> {
+    let (path, out) = match (args.positional.as_slice(), args.value("-o")) {
+        ([path], Some(out)) => (path, out),
+        _ => usage_error("anonymize needs one file and -o")
+    };
+
+    let dates = match (args.value("--shift-days"), args.has("--strip-dates")) {
+        (Some(_), true) => usage_error("--shift-days and --strip-dates don't go together"),
+        (Some(days), false) => DateChange::Shift(days.parse().unwrap_or_else(|_| usage_error(&format!("bad day count {}", days)))),
+        (None, true) => DateChange::Strip,
+        (None, false) => DateChange::Keep,
+    };
+    let options = Anonymize {
+        tail_number: args.value("--tail").unwrap_or("N00000").to_owned(),
+        dates,
+    };
+
+    let file = open_file(path, args.has("--mmap"))?;
+    let mut writer = BufWriter::new(File::create(out)?);
+    anonymize::anonymize(&mut writer, &file, &options)?;
+    writer.flush()
+}
+
+fn verify(args: &Args) -> io::Result<()> {
+    if args.positional.is_empty() {
+        usage_error("verify needs a file");
+    }
+
+    let mut problems = 0;
+    for path in &args.positional {
+        let file = match open_file(path, args.has("--mmap")) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("{}: {}", path, e);
+                problems += 1;
+                continue;
+            }
+        };
+
+        let warnings = validate(&file);
+        for warning in &warnings {
+            println!("{}: {}", path, warning);
+        }
+        problems += warnings.len();
+
+        for (i, info) in file.flights().enumerate() {
+            if let Err(e) = file.decode_flight(i) {
+                println!("{}: flight {} doesn't decode: {}", path, info.flight_number, e);
+                problems += 1;
+            }
         }
     }
 
-    let mut first_flight_len = 0usize;
-    for record in &header_records {
-        if let D(info) = record {
-            first_flight_len = (info.length * 2) as usize; // convert number of shorts to number of bytes
+    if problems > 0 {
+        println!("{} problems", problems);
+        process::exit(1);
+    }
+    Ok(())
+}
+
+fn plot(args: &Args) -> io::Result<()> {
+    let (path, out) = match (args.positional.as_slice(), args.value("-o")) {
+        ([path], Some(out)) => (path, out),
+        _ => usage_error("plot needs one file and -o")
+    };
+    let flight_number = match args.value("--flight").map(str::parse::<u16>) {
+        Some(Ok(n)) => n,
+        _ => usage_error("plot needs --flight N")
+    };
+    let channels = parse_channel_list(args.value("--channels").unwrap_or("E1-E6"))
+        .unwrap_or_else(|e| usage_error(&e));
+
+    let selection = Selection { all: false, flights: vec![flight_number] };
+    let (headers, config, flights) = load(path, &selection, args.has("--mmap"))?;
+    let flight = flights.first().unwrap_or_else(|| usage_error(&format!("no flight {} in {}", flight_number, path)));
+    let limits = headers.iter().find_map(|h| match h.record() {
+        HeaderRecord::A(limits) => Some(limits),
+        _ => None
+    });
+
+    let svg = plot::svg(flight, num_engines(&config), &channels, limits).unwrap_or_else(|e| usage_error(&e));
+    if out.to_ascii_lowercase().ends_with(".png") {
+        #[cfg(feature = "png")]
+        return std::fs::write(out, plot::png(&svg).map_err(io::Error::other)?);
+        #[cfg(not(feature = "png"))]
+        usage_error("png output needs jpi to be built with the png feature");
+    }
+    std::fs::write(out, svg)
+}
+
+fn lean(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "lean");
+    for path in &args.positional {
+        let (_, config, flights) = load(path, &selection, args.has("--mmap"))?;
+        for flight in &flights {
+            for find in lean_finds(flight, num_engines(&config)) {
+                let at = |sample: usize| DateTime::from_unix_seconds(
+                    flight.header.start().unix_seconds() + flight.elapsed_secs()[sample] as i64);
+                println!("flight {} engine {}: lean find {} to {}, {:.1} to {:.1} gph",
+                         flight.info.flight_number, find.engine + 1, at(find.start_sample), at(find.end_sample),
+                         find.start_ff, find.end_ff);
+                if find.rich_of_peak() {
+                    println!("    no cylinder peaked, stayed rich of peak");
+                }
+                for peak in &find.peaks {
+                    println!("    {}", peak);
+                }
+                if let Some(spread) = find.gami_spread() {
+                    println!("    GAMI spread {:.1} gph", spread);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn phases(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "phases");
+    let model = engine_model(args)?;
+    for path in &args.positional {
+        let (headers, config, flights) = load(path, &selection, args.has("--mmap"))?;
+        let limits = headers.iter().find_map(|h| match h.record() {
+            HeaderRecord::A(limits) => Some(limits),
+            _ => None
+        });
+
+        for flight in &flights {
+            let elapsed = flight.elapsed_secs();
+            let start = flight.header.start().unix_seconds();
+            println!("flight {}", flight.info.flight_number);
+            for report in phase_reports(flight, num_engines(&config), limits, model.as_ref()) {
+                let segment = report.segment;
+                let secs = elapsed[segment.end_sample] - elapsed[segment.start_sample] + flight.header.interval_secs as u32;
+                println!("    {} from {} for {}m{:02}s", segment.phase,
+                         DateTime::from_unix_seconds(start + elapsed[segment.start_sample] as i64), secs / 60, secs % 60);
+
+                // the hottest of each group and the average fuel flow say the most about a phase
+                let hottest = |channels: std::ops::Range<usize>| report.channels.iter()
+                    .filter(|s| channels.contains(&s.channel))
+                    .max_by_key(|s| s.max);
+                let mut line = Vec::new();
+                for (label, stats) in [("EGT", hottest(0..6)), ("CHT", hottest(8..14)), ("TIT", hottest(6..8)), ("oil", hottest(15..16))].iter() {
+                    if let Some(stats) = stats {
+                        line.push(format!("max {} {} ({})", label, stats.max, CHANNEL_NAMES[stats.channel]));
+                    }
+                }
+                if let Some(ff) = report.channels.iter().find(|s| s.channel == 23) {
+                    line.push(format!("mean FF {:.1} gph", ff.mean / channel_scale(23)));
+                }
+                if let Some((mean, max)) = report.percent_power {
+                    line.push(format!("power {:.0}% (max {:.0}%)", mean, max));
+                }
+                if !line.is_empty() {
+                    println!("        {}", line.join(", "));
+                }
+
+                for exceedance in &report.exceedances {
+                    let (word, limit) = match exceedance.limit {
+                        Limit::Above(limit) => ("above", limit),
+                        Limit::Below(limit) => ("below", limit),
+                    };
+                    println!("        {} {} {} for {} samples, worst {}", CHANNEL_NAMES[exceedance.channel], word, limit,
+                             exceedance.samples, exceedance.worst);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn trend(args: &Args) -> io::Result<()> {
+    if args.positional.is_empty() {
+        usage_error("trend needs a file");
+    }
+    let last = match args.value("--last").map(str::parse::<usize>) {
+        Some(Ok(n)) if n > 0 => n,
+        None => 20,
+        _ => usage_error("--last needs a number of flights")
+    };
+
+    // merging drops the flights that turn up in more than one download
+    let files = args.positional.iter().map(|p| open_file(p, args.has("--mmap"))).collect::<io::Result<Vec<_>>>()?;
+    let merged = merge::merge(&files)?;
+    let engines = merged.headers.iter().find_map(|h| match h.record() {
+        HeaderRecord::C(config) => Some(num_engines(config)),
+        _ => None
+    }).unwrap_or(1);
+
+    let mut averages = Vec::new();
+    for flight in merged.flights.iter().rev() {
+        if averages.len() == last {
             break;
         }
+        if let Some(cruise) = cruise_averages(&flight.decode(&files)?, engines) {
+            averages.push(cruise);
+        }
+    }
+    averages.reverse();
+    if averages.is_empty() {
+        println!("no flight reached cruise");
+    }
+
+    for cruise in &averages {
+        let readings = |r: &[(usize, f64)]| r.iter().map(|(c, v)| format!("{} {:.0}", CHANNEL_NAMES[*c], v)).collect::<Vec<_>>().join(" ");
+        println!("flight {} {} ({}m cruise)", cruise.flight_number, cruise.start, cruise.cruise_secs / 60);
+        println!("    {}", readings(&cruise.egt));
+        println!("    {}", readings(&cruise.cht));
+        let mut line = Vec::new();
+        if let Some(spread) = cruise.egt_spread() {
+            line.push(format!("EGT spread {:.0}", spread));
+        }
+        if let Some(spread) = cruise.cht_spread() {
+            line.push(format!("CHT spread {:.0}", spread));
+        }
+        if let Some(oil) = cruise.oil {
+            line.push(format!("oil {:.0}", oil));
+        }
+        if !line.is_empty() {
+            println!("    {}", line.join(", "));
+        }
+    }
+
+    for drift in drifting(&averages) {
+        println!("DRIFT: {}", drift);
+    }
+    Ok(())
+}
+
+fn faults(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "faults");
+    for path in &args.positional {
+        let (_, config, flights) = load(path, &selection, args.has("--mmap"))?;
+        for flight in &flights {
+            let start = flight.header.start().unix_seconds();
+            let elapsed = flight.elapsed_secs();
+            for fault in sensor_faults(flight, num_engines(&config)) {
+                println!("flight {} at {}: {}", flight.info.flight_number,
+                         DateTime::from_unix_seconds(start + elapsed[fault.start_sample] as i64), fault);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn fuel(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "fuel");
+    let path = match args.positional.as_slice() {
+        [path] => path,
+        _ => usage_error("fuel needs one file")
+    };
+    let actual = args.value("--actual").map(|a| match a.parse::<f64>() {
+        Ok(gallons) if gallons > 0.0 => gallons,
+        _ => usage_error("--actual needs a number of gallons")
+    });
+
+    let (headers, config, flights) = load(path, &selection, args.has("--mmap"))?;
+    let engines = num_engines(&config);
+    let mut indicated = [0.0; 2];
+    for flight in &flights {
+        for used in fuel_used(flight, engines) {
+            let engine = if engines == 2 { ["left ", "right "][used.engine] } else { "" };
+            print!("flight {}: {}FF adds up to {:.1} gal", flight.info.flight_number, engine, used.integrated);
+            match (used.recorded, used.discrepancy()) {
+                (Some(recorded), Some(difference)) if recorded > 0.0 =>
+                    println!(", USD counted {:.1} gal ({:+.1} gal, {:+.1}%)", recorded, difference, difference * 100.0 / recorded),
+                (Some(recorded), _) => println!(", USD counted {:.1} gal", recorded),
+                (None, _) => println!(", no USD recorded")
+            }
+            indicated[used.engine] += used.indicated();
+        }
     }
-    let config = header_records.iter().find_map(|h| match h {
-        C(cfg) => Some(cfg),
+
+    let actual = match actual {
+        Some(actual) => actual,
+        None => return Ok(())
+    };
+    let limits = headers.iter().find_map(|h| match h.record() {
+        HeaderRecord::F(limits) => Some(limits),
         _ => None
-    }).unwrap();
-
-    println!("position {}", reader.stream_position().unwrap());
-    let header = read_flight_header(&mut reader).unwrap();
-    println!("{:?}", &header);
-    println!("position {}", reader.stream_position().unwrap());
-    println!("sizeof flightheader {}", size_of::<flightheader>());
-
-    let init = binary_record::new(&config);
-
-    let mut flight_data = vec![0u8; first_flight_len];
-    reader.read(flight_data.as_mut_slice()).unwrap();
-    let i = flight_data.as_slice();
-    let (i, data1) = parse_binary_record(&init, i, &config, &header).unwrap();
-    let (i, data2) = parse_binary_record(&data1, i, &config, &header).unwrap();
-    println!("{:?}", &data1.data);
-    println!("{:?}", &data2.data);
+    }).unwrap_or_else(|| usage_error(&format!("{} has no $F record with the K-factor", path)));
+
+    // a fill-up can't tell the engines of a twin apart, so both get the same correction
+    let total = indicated.iter().sum::<f64>();
+    println!("EDM showed {:.1} gal used, the fill-up took {:.1} gal ({:+.1}%)", total, actual, (total - actual) * 100.0 / actual);
+    for (engine, &k_factor) in [limits.k_factor, limits.k_factor2].iter().enumerate().take(engines as usize) {
+        match corrected_k_factor(k_factor, total, actual) {
+            Some(corrected) => println!("K-factor {}: {:.2} -> {:.2}", engine + 1, k_factor as f64 / 100.0, corrected as f64 / 100.0),
+            None => println!("K-factor {}: no fuel used to correct it by", engine + 1)
+        }
+    }
+    Ok(())
+}
+
+fn alarms(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "alarms");
+    let mut settings = AlarmSettings::default();
+    if let Some(delay) = args.value("--delay") {
+        settings.delay_secs = delay.parse().unwrap_or_else(|_| usage_error("--delay needs a number of seconds"));
+    }
+    if let Some(hysteresis) = args.value("--hysteresis") {
+        settings.hysteresis = hysteresis.parse().unwrap_or_else(|_| usage_error("--hysteresis needs a number of degrees"));
+    }
+
+    for path in &args.positional {
+        let (headers, config, flights) = load(path, &selection, args.has("--mmap"))?;
+        let limits = match headers.iter().find_map(|h| match h.record() {
+            HeaderRecord::A(limits) => Some(limits),
+            _ => None
+        }) {
+            Some(limits) => limits,
+            None => {
+                eprintln!("{}: no $A record, so no limits to alarm on", path);
+                continue;
+            }
+        };
+
+        for flight in &flights {
+            let start = flight.header.start().unix_seconds();
+            let elapsed = flight.elapsed_secs();
+            println!("flight {}", flight.info.flight_number);
+            for alarm in replay(flight, num_engines(&config), limits, &settings) {
+                let on = elapsed[alarm.start_sample];
+                let off = alarm.end_sample.map_or_else(|| "still on at shutdown".to_owned(),
+                                                      |end| format!("for {}s", elapsed[end] - on));
+                println!("    {} {} {}", DateTime::from_unix_seconds(start + on as i64), alarm, off);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cooling(args: &Args) -> io::Result<()> {
+    let selection = Selection::from_args(args, "cooling");
+    for path in &args.positional {
+        let (headers, config, flights) = load(path, &selection, args.has("--mmap"))?;
+        let limits = headers.iter().find_map(|h| match h.record() {
+            HeaderRecord::A(limits) => Some(limits),
+            _ => None
+        });
+        let limits = match limits {
+            Some(limits) => limits,
+            None => {
+                eprintln!("{}: no $A record, so no shock cooling limit to check against", path);
+                continue;
+            }
+        };
+
+        for flight in &flights {
+            for event in shock_cooling(flight, num_engines(&config), limits) {
+                let at = DateTime::from_unix_seconds(
+                    flight.header.start().unix_seconds() + flight.elapsed_secs()[event.start_sample] as i64);
+                println!("flight {} at {}: {} (limit {}°/min)", flight.info.flight_number, at, event, limits.cld);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "sqlite")]
+fn import(args: &Args) -> io::Result<()> {
+    let path = args.value("--db").unwrap_or_else(|| usage_error("import needs --db"));
+    if args.positional.is_empty() {
+        usage_error("import needs a file");
+    }
+
+    let mut conn = db::open(path).map_err(io::Error::other)?;
+    let everything = Selection { all: true, flights: Vec::new() };
+    for file in &args.positional {
+        let (headers, config, flights) = load(file, &everything, args.has("--mmap"))?;
+        let stats = db::import(&mut conn, &headers, &config, &flights).map_err(io::Error::other)?;
+        println!("{}: imported {} flights, {} already in {}", file, stats.flights, stats.duplicates, path);
+    }
+
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let command = args.next().unwrap_or_else(|| usage_error("missing command"));
+
+    match command.as_str() {
+        "print" => print(&Args::parse(args, &[])),
+        "info" => info(&Args::parse(args, &[])),
+        "export" => export(&Args::parse(args, &["--flight", "--format", "--derived", "--engine", "-o"])),
+        "resample" => resample_csv(&Args::parse(args, &["--flight", "--every", "--channels", "-o"])),
+        "dump" => dump_file(&Args::parse(args, &["--flight", "-o"])),
+        "merge" => merge(&Args::parse(args, &["-o"])),
+        "extract" => extract(&Args::parse(args, &["--flight", "-o"])),
+        "verify" => verify(&Args::parse(args, &[])),
+        "lean" => lean(&Args::parse(args, &["--flight"])),
+        "phases" => phases(&Args::parse(args, &["--flight", "--engine"])),
+        "trend" => trend(&Args::parse(args, &["--last"])),
+        "faults" => faults(&Args::parse(args, &["--flight"])),
+        "fuel" => fuel(&Args::parse(args, &["--flight", "--actual"])),
+        "alarms" => alarms(&Args::parse(args, &["--flight", "--delay", "--hysteresis"])),
+        "cooling" => cooling(&Args::parse(args, &["--flight"])),
+        "plot" => plot(&Args::parse(args, &["--flight", "--channels", "-o"])),
+        "anonymize" => anonymize(&Args::parse(args, &["--tail", "--shift-days", "-o"])),
+        #[cfg(feature = "sqlite")]
+        "import" => import(&Args::parse(args, &["--db"])),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => usage_error(&format!("unknown command {}", command))
+    }
 }
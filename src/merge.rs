@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::data::{flightheader, DateTime};
+use crate::file::{Flight, JpiFile};
+use crate::headers::{tail_number, HeaderRecord};
+use crate::writer::write_jpi;
+
+// one flight of a merged set and where its copies came from. sources and conflicts index the files
+// handed to merge
+#[derive(Clone, Debug, PartialEq)]
+pub struct MergedFlight<'a> {
+    pub header: flightheader,
+    pub data: &'a [u8],
+    pub sources: Vec<usize>, // files with a copy identical to `data`
+    pub conflicts: Vec<usize>, // files whose copy has the same flight header but different data
+    location: (usize, usize), // file and flight index `data` came from
+}
+
+impl MergedFlight<'_> {
+    pub fn flight_number(&self) -> u16 {
+        self.header.flightnumber
+    }
+
+    pub fn start(&self) -> DateTime {
+        self.header.start()
+    }
+
+    // decodes the kept copy. `files` has to be the slice that was handed to merge
+    pub fn decode(&self, files: &[JpiFile]) -> io::Result<Flight> {
+        let (file, index) = self.location;
+        files[file].decode_flight(index)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Merged<'a> {
+    pub headers: Vec<HeaderRecord>, // from the most recent download
+    pub flights: Vec<MergedFlight<'a>>, // oldest first
+}
+
+impl Merged<'_> {
+    pub fn conflicts(&self) -> impl Iterator<Item = &MergedFlight<'_>> {
+        self.flights.iter().filter(|f| !f.conflicts.is_empty())
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let flights = self.flights.iter().map(|f| (f.flight_number(), f.data)).collect::<Vec<_>>();
+        write_jpi(out, &self.headers, &flights)
+    }
+}
+
+// folds several downloads of the same aircraft into one set of flights. copies are matched by
+// flight header, and count as the same flight if their bytes or decoded records match. when copies
+// disagree the longer one is kept and the rest are listed as conflicts. flights are copied as they
+// are, so every download has to use the same checksum
+pub fn merge(files: &[JpiFile]) -> io::Result<Merged<'_>> {
+    let newest = files.iter().max_by_key(|f| f.headers().iter().find_map(|h| match h.record() {
+        HeaderRecord::T(t) => Some(t.datetime()),
+        _ => None
+    })).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "nothing to merge"))?;
+
+    let tail = tail_number(newest.headers());
+    if let Some(other) = files.iter().find(|f| tail_number(f.headers()) != tail) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("can't merge {:?} with {:?}", tail, tail_number(other.headers()))));
+    }
+
+    let checksum = newest.protocol().checksum;
+    if let Some(other) = files.iter().find(|f| f.protocol().checksum != checksum) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("can't merge {:?} checksums with {:?}", checksum, other.protocol().checksum)));
+    }
+
+    let mut flights: Vec<MergedFlight> = Vec::new();
+    let mut by_header = HashMap::new(); // flight header to its index in `flights`
+    for (f, file) in files.iter().enumerate() {
+        for i in 0..file.flights().count() {
+            let header = file.flight_header(i)?;
+            let data = file.flight_data(i);
+
+            let existing = match by_header.get(&header) {
+                Some(&m) => &mut flights[m],
+                None => {
+                    by_header.insert(header, flights.len());
+                    flights.push(MergedFlight { header, data, sources: vec![f], conflicts: Vec::new(), location: (f, i) });
+                    continue;
+                }
+            };
+
+            let (kept_file, kept_index) = existing.location;
+            if existing.data == data || files[kept_file].decode_flight(kept_index)?.records == file.decode_flight(i)?.records {
+                existing.sources.push(f);
+            } else if data.len() > existing.data.len() {
+                existing.conflicts.append(&mut existing.sources);
+                existing.sources.push(f);
+                existing.data = data;
+                existing.location = (f, i);
+            } else {
+                existing.conflicts.push(f);
+            }
+        }
+    }
+
+    flights.sort_by_key(|m| (m.start(), m.flight_number()));
+
+    Ok(Merged {
+        headers: newest.headers().to_vec(),
+        flights
+    })
+}
+
+#[test]
+fn test_merge() {
+    use crate::file::{test_download, test_flight, TEST_RECORDS};
+
+    let flags = 0x1831F8FD;
+    let longer: [&[u8]; 3] = [TEST_RECORDS[0], TEST_RECORDS[1], &[1, 1, 0, 0b1, 0, 5]];
+    let different: [&[u8]; 2] = [&[1, 1, 0, 0b1, 0, 20], TEST_RECORDS[1]];
+    let files = vec![
+        test_download(&[test_flight(1, flags, &TEST_RECORDS), test_flight(2, flags, &TEST_RECORDS)]),
+        // the same first flight, and the second one before its download was cut short
+        test_download(&[test_flight(1, flags, &TEST_RECORDS), test_flight(2, flags, &longer)]),
+        // a first flight with the same header that reads differently
+        test_download(&[test_flight(1, flags, &different)]),
+    ].into_iter().map(|bytes| JpiFile::from_bytes(bytes).unwrap()).collect::<Vec<_>>();
+
+    let merged = merge(&files).unwrap();
+    assert_eq!(merged.flights.len(), 2);
+    assert_eq!((&merged.flights[0].sources[..], &merged.flights[0].conflicts[..]), (&[0, 1][..], &[2][..]));
+    assert_eq!((&merged.flights[1].sources[..], &merged.flights[1].conflicts[..]), (&[1][..], &[0][..]));
+    assert_eq!(merged.flights[1].decode(&files).unwrap().records.len(), 4);
+    assert_eq!(merged.conflicts().count(), 2);
+
+    let mut bytes = Vec::new();
+    merged.write(&mut bytes).unwrap();
+    let written = JpiFile::from_bytes(bytes).unwrap();
+    assert_eq!(written.decode_flight(1).unwrap().records, merged.flights[1].decode(&files).unwrap().records);
+}
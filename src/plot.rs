@@ -0,0 +1,161 @@
+use std::fmt::Write;
+
+use crate::data::{installed_channels, DateTime, CHANNEL_NAMES};
+use crate::file::Flight;
+use crate::headers::ConfiguredLimits;
+
+const WIDTH: f64 = 1000.0;
+const HEIGHT: f64 = 500.0;
+const LEFT: f64 = 60.0;
+const RIGHT: f64 = 110.0; // room for the legend
+const TOP: f64 = 30.0;
+const BOTTOM: f64 = 40.0;
+
+const COLORS: [&str; 10] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd",
+    "#8c564b", "#e377c2", "#7f7f7f", "#bcbd22", "#17becf",
+];
+
+// the configured limits that apply to any of `channels`, as (label, raw value) pairs
+fn limit_lines(channels: &[usize], engines: u32, limits: &ConfiguredLimits) -> Vec<(&'static str, f64)> {
+    let has = |slots: &[usize]| channels.iter().any(|c| slots.contains(c));
+    let mut lines = Vec::new();
+
+    if has(&[8, 9, 10, 11, 12, 13, 32, 33, 34, 35, 36, 37]) {
+        lines.push(("CHT", limits.cht as f64));
+    }
+    // 30 is the right engine's first TIT on a twin and HP on a single
+    if has(&[6, 7, 31]) || (engines == 2 && has(&[30])) {
+        lines.push(("TIT", limits.tit as f64));
+    }
+    if has(&[14, 38]) {
+        lines.push(("CLD", limits.cld as f64));
+    }
+    if has(&[15, 39]) {
+        lines.push(("OIL HI", limits.oil_hi as f64));
+        lines.push(("OIL LO", limits.oil_lo as f64));
+    }
+    if has(&[20]) { // both in tenths of a volt
+        lines.push(("VOLTS HI", limits.volts_hi_times_ten as f64));
+        lines.push(("VOLTS LO", limits.volts_lo_times_ten as f64));
+    }
+
+    lines
+}
+
+// a step of 1, 2 or 5 times a power of ten that gives roughly `ticks` divisions
+fn nice_step(range: f64, ticks: f64) -> f64 {
+    let raw = (range / ticks).max(f64::MIN_POSITIVE);
+    let magnitude = 10f64.powf(raw.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter().map(|m| m * magnitude).find(|&s| s >= raw).unwrap_or(10.0 * magnitude)
+}
+
+// a time series chart of `channels` over the flight, with the configured limits as dashed lines.
+// samples where a channel isn't available leave a gap. a channel the flight's flags say isn't
+// installed is an error, it would only draw the 0xF0 every channel starts from
+pub fn svg(flight: &Flight, engines: u32, channels: &[usize], limits: Option<&ConfiguredLimits>) -> Result<String, String> {
+    let installed = installed_channels(flight.header.flags, engines);
+    if let Some(&c) = channels.iter().find(|c| !installed.contains(c)) {
+        return Err(format!("{} isn't installed for flight {}", CHANNEL_NAMES[c], flight.info.flight_number));
+    }
+
+    let start = flight.header.start().unix_seconds();
+    let elapsed = flight.elapsed_secs();
+    let duration = elapsed.last().copied().unwrap_or(0).max(1) as f64;
+    let lines = limits.map(|l| limit_lines(channels, engines, l)).unwrap_or_default();
+
+    let values = flight.records.iter()
+        .flat_map(|r| channels.iter().filter(move |&&c| r.available(c)).map(move |&c| r.data.values()[c] as f64))
+        .chain(lines.iter().map(|(_, v)| *v));
+    let (lo, hi) = values.fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    let (lo, hi) = if lo > hi { (0.0, 1.0) } else { (lo, hi.max(lo + 1.0)) };
+    let step = nice_step(hi - lo, 8.0);
+    let (lo, hi) = ((lo / step).floor() * step, (hi / step).ceil() * step);
+
+    let plot_w = WIDTH - LEFT - RIGHT;
+    let plot_h = HEIGHT - TOP - BOTTOM;
+    let x = |secs: f64| LEFT + secs / duration * plot_w;
+    let y = |v: f64| TOP + (hi - v) / (hi - lo) * plot_h;
+
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}" font-family="Helvetica, Arial, DejaVu Sans, sans-serif" font-size="11">"#,
+                     WIDTH, HEIGHT, WIDTH, HEIGHT);
+    let _ = writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(out, r#"<text x="{}" y="18" font-size="13">flight {} {}</text>"#, LEFT, flight.info.flight_number, flight.header.start());
+
+    // grid and axis labels
+    let mut v = lo;
+    while v <= hi + step / 2.0 {
+        let _ = writeln!(out, r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#ddd"/>"##, LEFT, y(v), LEFT + plot_w, y(v));
+        let _ = writeln!(out, r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{}</text>"#, LEFT - 5.0, y(v) + 4.0, v);
+        v += step;
+    }
+    let tick = nice_step(duration / 60.0, 10.0).max(1.0) * 60.0;
+    let mut t = 0.0;
+    while t <= duration {
+        let at = DateTime::from_unix_seconds(start + t as i64);
+        let _ = writeln!(out, r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#ddd"/>"##, x(t), TOP, x(t), TOP + plot_h);
+        let _ = writeln!(out, r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{:02}:{:02}</text>"#, x(t), TOP + plot_h + 15.0, at.hour, at.minute);
+        t += tick;
+    }
+    let _ = writeln!(out, r#"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="black"/>"#, LEFT, TOP, plot_w, plot_h);
+
+    for (label, value) in &lines {
+        let _ = writeln!(out, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="red" stroke-dasharray="6 4"/>"#,
+                         LEFT, y(*value), LEFT + plot_w, y(*value));
+        let _ = writeln!(out, r#"<text x="{:.1}" y="{:.1}" fill="red">{}</text>"#, LEFT + 4.0, y(*value) - 3.0, label);
+    }
+
+    for (n, &c) in channels.iter().enumerate() {
+        let color = COLORS[n % COLORS.len()];
+        let mut path = String::new();
+        let mut pen_down = false;
+        for (record, &secs) in flight.records.iter().zip(elapsed.iter()) {
+            if !record.available(c) {
+                pen_down = false;
+                continue;
+            }
+            let _ = write!(path, "{}{:.1},{:.1} ", if pen_down { "L" } else { "M" }, x(secs as f64), y(record.data.values()[c] as f64));
+            pen_down = true;
+        }
+        let _ = writeln!(out, r#"<path d="{}" fill="none" stroke="{}" stroke-width="1.2"/>"#, path.trim_end(), color);
+
+        let ly = TOP + 10.0 + n as f64 * 16.0;
+        let _ = writeln!(out, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="{}" stroke-width="2"/>"#,
+                         LEFT + plot_w + 10.0, ly, LEFT + plot_w + 30.0, ly, color);
+        let _ = writeln!(out, r#"<text x="{:.1}" y="{:.1}">{}</text>"#, LEFT + plot_w + 35.0, ly + 4.0, CHANNEL_NAMES[c]);
+    }
+
+    out.push_str("</svg>\n");
+    Ok(out)
+}
+
+// rasterizes a chart from svg()
+#[cfg(feature = "png")]
+pub fn png(svg: &str) -> Result<Vec<u8>, String> {
+    let mut options = resvg::usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = resvg::usvg::Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+
+    let size = tree.size().to_int_size();
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height()).ok_or("empty chart")?;
+    resvg::render(&tree, resvg::tiny_skia::Transform::default(), &mut pixmap.as_mut());
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_svg() {
+    use crate::file::{test_download, test_flight, JpiFile, TEST_RECORDS};
+
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS)])).unwrap();
+    let flight = file.decode_flight(0).unwrap();
+    let chart = svg(&flight, 1, &[0, 6], None).unwrap();
+    assert!(chart.contains(">E1</text>") && chart.contains(">T1</text>"));
+    // no RPM probe on this flight
+    assert_eq!(svg(&flight, 1, &[0, 41], None), Err("RPM isn't installed for flight 1".to_owned()));
+
+    // the right engine's TIT gets the TIT limit on a twin
+    let limits = ConfiguredLimits { tit: 1650, ..Default::default() };
+    assert!(svg(&flight, 2, &[30], Some(&limits)).unwrap().contains(">TIT</text>"));
+    assert!(!svg(&flight, 2, &[0], Some(&limits)).unwrap().contains(">TIT</text>"));
+}
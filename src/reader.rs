@@ -0,0 +1,116 @@
+use std::io::{self, BufRead, Seek, SeekFrom};
+
+use crate::data::FlightDecoder;
+use crate::headers::{read_header_records, ConfigInfo, FlightInfo, HeaderRecord, Protocol};
+
+// walks a JPI download front to back without ever holding more than one record in memory,
+// so anything that implements BufRead (stdin, sockets, decompressors) works as a source
+pub struct JpiReader<R: BufRead> {
+    reader: R,
+    headers: Vec<HeaderRecord>,
+    config: ConfigInfo,
+    protocol: Protocol,
+    flights: Vec<FlightInfo>,
+    data_start: u64, // offset of the first flight from where the reader started
+    next_flight: usize,
+}
+
+impl<R: BufRead> JpiReader<R> {
+    pub fn new(mut reader: R) -> io::Result<JpiReader<R>> {
+        let (headers, data_start) = read_header_records(&mut reader)?;
+
+        let config = headers.iter().find_map(|h| match h.record() {
+            HeaderRecord::C(cfg) => Some(*cfg),
+            _ => None
+        }).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing $C record"))?;
+        // only what's already buffered is looked at if the checksum has to be worked out from the data
+        let protocol = Protocol::detect(&headers, &config, reader.fill_buf()?);
+
+        let flights = headers.iter().filter_map(|h| match h.record() {
+            HeaderRecord::D(info) => Some(*info),
+            _ => None
+        }).collect();
+
+        Ok(JpiReader {
+            reader,
+            headers,
+            config,
+            protocol,
+            flights,
+            data_start,
+            next_flight: 0
+        })
+    }
+
+    pub fn headers(&self) -> &[HeaderRecord] {
+        &self.headers
+    }
+
+    pub fn config(&self) -> &ConfigInfo {
+        &self.config
+    }
+
+    pub fn protocol(&self) -> &Protocol {
+        &self.protocol
+    }
+
+    // the $D directory, in the order the flights appear in the file
+    pub fn flights(&self) -> &[FlightInfo] {
+        &self.flights
+    }
+
+    // flights have to be consumed in order, dropping a decoder skips whatever is left of its flight
+    pub fn next_flight(&mut self) -> Option<io::Result<(FlightInfo, FlightDecoder<&mut R>)>> {
+        let info = *self.flights.get(self.next_flight)?;
+        self.next_flight += 1;
+        Some(FlightDecoder::new(&mut self.reader, &self.config, self.protocol.checksum, info.length).map(|d| (info, d)))
+    }
+}
+
+impl<R: BufRead + Seek> JpiReader<R> {
+    // jumps straight to the flight at `index` in the $D directory. the reader must have been
+    // at the start of the file when this JpiReader was created
+    pub fn seek_flight(&mut self, index: usize) -> io::Result<(FlightInfo, FlightDecoder<&mut R>)> {
+        let info = *self.flights.get(index)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such flight"))?;
+        let offset = self.flights[..index].iter().map(|f| f.length as u64 * 2).sum::<u64>();
+
+        self.reader.seek(SeekFrom::Start(self.data_start + offset))?;
+        self.next_flight = index + 1;
+        Ok((info, FlightDecoder::new(&mut self.reader, &self.config, self.protocol.checksum, info.length)?))
+    }
+}
+
+#[test]
+fn test_jpi_reader() {
+    use std::io::{BufReader, Cursor};
+    use crate::file::{test_download, test_flight, Flight, JpiFile, TEST_RECORDS};
+
+    let bytes = test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS), test_flight(2, 0x1831F8FD, &[&[1, 1, 0, 0b1, 0, 20]]),
+                                test_flight(3, 0x1831F8FD, &TEST_RECORDS)]);
+    let expected = JpiFile::from_bytes(bytes.clone()).unwrap().decode_all().unwrap();
+    fn decode<R: io::Read>((info, decoder): (FlightInfo, FlightDecoder<R>)) -> Flight {
+        Flight {
+            info,
+            header: *decoder.header(),
+            flag_difference: decoder.flag_difference(),
+            records: decoder.collect::<io::Result<_>>().unwrap(),
+        }
+    }
+
+    // a buffer smaller than a record, with flight 1 given up after its first record
+    let mut reader = JpiReader::new(BufReader::with_capacity(4, &bytes[..])).unwrap();
+    assert_eq!(reader.flights().len(), 3);
+    let (_, mut first) = reader.next_flight().unwrap().unwrap();
+    assert_eq!(first.next().unwrap().unwrap(), expected[0].records[0]);
+    drop(first);
+    assert_eq!(decode(reader.next_flight().unwrap().unwrap()), expected[1]);
+    assert_eq!(decode(reader.next_flight().unwrap().unwrap()), expected[2]);
+    assert!(reader.next_flight().is_none());
+
+    let mut reader = JpiReader::new(Cursor::new(&bytes)).unwrap();
+    assert_eq!(decode(reader.seek_flight(1).unwrap()), expected[1]);
+    assert_eq!(decode(reader.next_flight().unwrap().unwrap()), expected[2]);
+    assert_eq!(decode(reader.seek_flight(0).unwrap()), expected[0]);
+    assert!(reader.seek_flight(3).is_err());
+}
@@ -0,0 +1,110 @@
+// puts samples onto a fixed time step so flights recorded at different intervals line up.
+// everything works on (seconds into the flight, value) pairs with None where a reading was
+// missing, which is what channel_values gives for a recorded channel and what derived
+// channels already are
+
+use crate::data::installed_channels;
+use crate::file::Flight;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+    Linear, // None if either neighbour is missing
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Min,
+    Max,
+    Mean,
+}
+
+// a channel's raw readings, None where naflags said it wasn't available and throughout if the
+// flight's flags say it isn't installed
+pub fn channel_values(flight: &Flight, engines: u32, channel: usize) -> Vec<Option<f64>> {
+    let installed = installed_channels(flight.header.flags, engines).contains(&channel);
+    flight.records.iter()
+        .map(|r| Some(r.data.values()[channel] as f64).filter(|_| installed && r.available(channel)))
+        .collect()
+}
+
+// the value every `step_secs` from 0 up to the last sample, None before the first one.
+// `elapsed` has to be ascending
+pub fn resample(elapsed: &[u32], values: &[Option<f64>], step_secs: u32, how: Interpolation) -> Vec<Option<f64>> {
+    let last = match elapsed.last() {
+        Some(&last) => last,
+        None => return Vec::new()
+    };
+    let step = step_secs.max(1);
+
+    (0..=last / step).map(|k| {
+        let t = k * step;
+        // the first sample at or after t
+        let after = elapsed.partition_point(|&e| e < t);
+        if elapsed[after] == t {
+            return values[after];
+        }
+        let before = after.checked_sub(1)?;
+        match how {
+            Interpolation::Nearest if t - elapsed[before] <= elapsed[after] - t => values[before],
+            Interpolation::Nearest => values[after],
+            Interpolation::Linear => {
+                let (a, b) = (values[before]?, values[after]?);
+                let fraction = (t - elapsed[before]) as f64 / (elapsed[after] - elapsed[before]) as f64;
+                Some(a + (b - a) * fraction)
+            }
+        }
+    }).collect()
+}
+
+// one value per `step_secs` window, combining every available reading that fell inside it.
+// a window without any reading is None
+pub fn downsample(elapsed: &[u32], values: &[Option<f64>], step_secs: u32, how: Aggregate) -> Vec<Option<f64>> {
+    let last = match elapsed.last() {
+        Some(&last) => last,
+        None => return Vec::new()
+    };
+    let step = step_secs.max(1);
+
+    let mut windows = vec![Vec::new(); (last / step) as usize + 1];
+    for (&t, value) in elapsed.iter().zip(values) {
+        if let Some(v) = value {
+            windows[(t / step) as usize].push(*v);
+        }
+    }
+
+    windows.into_iter().map(|window| {
+        if window.is_empty() {
+            return None;
+        }
+        Some(match how {
+            Aggregate::Min => window.iter().copied().fold(f64::INFINITY, f64::min),
+            Aggregate::Max => window.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            Aggregate::Mean => window.iter().sum::<f64>() / window.len() as f64,
+        })
+    }).collect()
+}
+
+#[test]
+fn test_resample() {
+    let elapsed = [0, 6, 12, 18];
+    let values = [Some(0.0), Some(60.0), None, Some(30.0)];
+
+    assert_eq!(resample(&elapsed, &values, 4, Interpolation::Nearest), vec![Some(0.0), Some(60.0), Some(60.0), None, Some(30.0)]);
+    assert_eq!(resample(&elapsed, &values, 2, Interpolation::Linear)[..4], [Some(0.0), Some(20.0), Some(40.0), Some(60.0)]);
+    assert_eq!(resample(&elapsed, &values, 2, Interpolation::Linear)[7], None);
+
+    assert_eq!(downsample(&elapsed, &values, 12, Aggregate::Max), vec![Some(60.0), Some(30.0)]);
+    assert_eq!(downsample(&elapsed, &values, 12, Aggregate::Mean), vec![Some(30.0), Some(30.0)]);
+    assert_eq!(downsample(&elapsed, &values, 5, Aggregate::Min), vec![Some(0.0), Some(60.0), None, Some(30.0)]);
+}
+
+#[test]
+fn test_channel_values() {
+    use crate::file::{test_download, test_flight, JpiFile, TEST_RECORDS};
+
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS)])).unwrap();
+    let flight = file.decode_flight(0).unwrap();
+    assert_eq!(channel_values(&flight, 1, 0), vec![Some(250.0); 3]);
+    assert_eq!(channel_values(&flight, 1, 41), vec![None; 3]); // no RPM probe
+}
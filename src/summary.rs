@@ -0,0 +1,82 @@
+use std::ops::Range;
+
+use crate::analysis::power::{percent_power, EngineModel};
+use crate::data::{installed_channels, DateTime};
+use crate::file::Flight;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelStats {
+    pub channel: usize, // index into CHANNEL_NAMES
+    pub min: i16,
+    pub max: i16,
+    pub mean: f64,
+    pub samples: usize, // how many records had the channel available
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlightSummary {
+    pub flight_number: u16,
+    pub start: DateTime,
+    pub duration_secs: u32,
+    pub samples: usize,
+    pub max_dif: [Option<i16>; 2],
+    pub percent_power: Option<(f64, f64)>, // mean and max, where the HP channel or an engine model says
+    pub channels: Vec<ChannelStats>, // installed channels that were ever available
+}
+
+impl FlightSummary {
+    pub fn channel(&self, channel: usize) -> Option<&ChannelStats> {
+        self.channels.iter().find(|c| c.channel == channel)
+    }
+}
+
+// min, max and mean of each installed channel over some of a flight's samples, leaving out
+// channels that were never available in that stretch
+pub fn channel_stats(flight: &Flight, engines: u32, samples: Range<usize>) -> Vec<ChannelStats> {
+    let records = &flight.records[samples];
+    let mut channels = Vec::new();
+    for c in installed_channels(flight.header.flags, engines) {
+        let mut values = records.iter().filter(|r| r.available(c)).map(|r| r.data.values()[c]);
+        let first = match values.next() {
+            Some(v) => v,
+            None => continue
+        };
+
+        let (mut min, mut max, mut sum, mut n) = (first, first, first as f64, 1usize);
+        for v in values {
+            min = min.min(v);
+            max = max.max(v);
+            sum += v as f64;
+            n += 1;
+        }
+        channels.push(ChannelStats { channel: c, min, max, mean: sum / n as f64, samples: n });
+    }
+    channels
+}
+
+pub fn summarize(flight: &Flight, engines: u32, model: Option<&EngineModel>) -> FlightSummary {
+    let channels = channel_stats(flight, engines, 0..flight.records.len());
+
+    let mut max_dif = [None; 2];
+    for (e, dif) in max_dif.iter_mut().enumerate().take(engines as usize) {
+        *dif = flight.records.iter().map(|r| r.dif[e]).max();
+    }
+
+    FlightSummary {
+        flight_number: flight.info.flight_number,
+        start: flight.header.start(),
+        duration_secs: flight.elapsed_secs().last().copied().unwrap_or(0),
+        samples: flight.records.len(),
+        max_dif,
+        percent_power: mean_and_max(&percent_power(flight, engines, model)),
+        channels
+    }
+}
+
+pub(crate) fn mean_and_max(values: &[Option<f64>]) -> Option<(f64, f64)> {
+    let known = values.iter().flatten().copied().collect::<Vec<_>>();
+    if known.is_empty() {
+        return None;
+    }
+    Some((known.iter().sum::<f64>() / known.len() as f64, known.iter().copied().fold(f64::NEG_INFINITY, f64::max)))
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use crate::data::DateTime;
+use crate::file::JpiFile;
+use crate::headers::{config_flags, HeaderRecord, Timestamp};
+
+// things that parse and checksum fine but can't be right
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    MissingRecord(char),
+    DuplicateRecord(char),
+    BadTimestamp(Timestamp),
+    InvertedLimits { name: &'static str, lo: u16, hi: u16 },
+    ZeroKFactor,
+    EmptyFlight(u16),
+    FlightOrder { previous: u16, next: u16 },
+    FlightTruncated { flight_number: u16, expected: usize, available: usize },
+    TrailingData(usize),
+    FlightNumberMismatch { directory: u16, header: u16 },
+    FlagsDiffer { flight_number: u16, config: u32, flight: u32 },
+    BadFlightDate { flight_number: u16, start: DateTime },
+    BadFlightHeader { flight_number: u16, error: String },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Warning::*;
+        match self {
+            MissingRecord(kind) => write!(f, "no ${} record", kind),
+            DuplicateRecord(kind) => write!(f, "more than one ${} record", kind),
+            BadTimestamp(t) => write!(f, "$T has an impossible date {:?}", t),
+            InvertedLimits { name, lo, hi } => write!(f, "$A {} low limit {} is above the high limit {}", name, lo, hi),
+            ZeroKFactor => write!(f, "$F fuel flow K-factor is zero"),
+            EmptyFlight(n) => write!(f, "$D flight {} has zero length", n),
+            FlightOrder { previous, next } => write!(f, "$D flight {} comes after flight {}", next, previous),
+            FlightTruncated { flight_number, expected, available } =>
+                write!(f, "flight {} should be {} bytes but only {} are in the file", flight_number, expected, available),
+            TrailingData(n) => write!(f, "{} bytes after the last flight", n),
+            FlightNumberMismatch { directory, header } =>
+                write!(f, "$D says flight {} but its flight header says {}", directory, header),
+            FlagsDiffer { flight_number, config, flight } =>
+                write!(f, "flight {} flags {:#010x} differ from $C flags {:#010x}", flight_number, flight, config),
+            BadFlightDate { flight_number, start } => write!(f, "flight {} has an impossible start {}", flight_number, start),
+            BadFlightHeader { flight_number, error } => write!(f, "flight {} header: {}", flight_number, error),
+        }
+    }
+}
+
+fn valid_date(year: u16, month: u16, day: u16, hour: u16, minute: u16) -> bool {
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => return false
+    };
+    (1..=days_in_month).contains(&day) && hour < 24 && minute < 60
+}
+
+pub fn validate_headers(headers: &[HeaderRecord]) -> Vec<Warning> {
+    use HeaderRecord::*;
+    let mut warnings = Vec::new();
+
+    for &kind in ['U', 'A', 'F', 'T', 'C', 'L'].iter() {
+        match headers.iter().filter(|h| h.kind() == kind).count() {
+            0 => warnings.push(Warning::MissingRecord(kind)),
+            1 => {}
+            _ => warnings.push(Warning::DuplicateRecord(kind)),
+        }
+    }
+
+    let mut previous: Option<u16> = None;
+    for header in headers {
+        match header.record() {
+            T(t) if !valid_date(2000 + t.year, t.month, t.day, t.hour, t.minute) => {
+                warnings.push(Warning::BadTimestamp(*t));
+            }
+            A(a) => {
+                if a.oil_lo > a.oil_hi {
+                    warnings.push(Warning::InvertedLimits { name: "oil", lo: a.oil_lo, hi: a.oil_hi });
+                }
+                if a.volts_lo_times_ten > a.volts_hi_times_ten {
+                    warnings.push(Warning::InvertedLimits { name: "volts", lo: a.volts_lo_times_ten, hi: a.volts_hi_times_ten });
+                }
+            }
+            F(ff) if ff.k_factor == 0 => {
+                warnings.push(Warning::ZeroKFactor);
+            }
+            D(d) => {
+                if d.length == 0 {
+                    warnings.push(Warning::EmptyFlight(d.flight_number));
+                }
+                if let Some(previous) = previous.filter(|&p| p >= d.flight_number) {
+                    warnings.push(Warning::FlightOrder { previous, next: d.flight_number });
+                }
+                previous = Some(d.flight_number);
+            }
+            _ => {}
+        }
+    }
+
+    warnings
+}
+
+// header checks plus everything that can be checked against the flights without decoding them
+pub fn validate(file: &JpiFile) -> Vec<Warning> {
+    let mut warnings = validate_headers(file.headers());
+    let config = config_flags(file.config());
+
+    let mut end = file.data().len();
+    for (i, info) in file.flights().enumerate() {
+        let range = file.flight_range(i);
+        end = range.end;
+        if range.end > file.data().len() {
+            warnings.push(Warning::FlightTruncated {
+                flight_number: info.flight_number,
+                expected: range.len(),
+                available: file.flight_data(i).len(),
+            });
+        }
+
+        let header = match file.flight_header(i) {
+            Ok(header) => header,
+            Err(e) => {
+                warnings.push(Warning::BadFlightHeader { flight_number: info.flight_number, error: e.to_string() });
+                continue;
+            }
+        };
+        if header.flightnumber != info.flight_number {
+            warnings.push(Warning::FlightNumberMismatch { directory: info.flight_number, header: header.flightnumber });
+        }
+        if header.flags != config {
+            warnings.push(Warning::FlagsDiffer { flight_number: info.flight_number, config, flight: header.flags });
+        }
+        let start = header.start();
+        if !valid_date(start.year, start.month, start.day, start.hour, start.minute) {
+            warnings.push(Warning::BadFlightDate { flight_number: info.flight_number, start });
+        }
+    }
+    if end < file.data().len() {
+        warnings.push(Warning::TrailingData(file.data().len() - end));
+    }
+
+    warnings
+}
+
+#[test]
+fn test_validate_headers() {
+    use crate::headers::{ConfiguredLimits, FlightInfo, FuelFlowLimits, LastHeaderRecord};
+    use HeaderRecord::*;
+
+    let limits = ConfiguredLimits { volts_hi_times_ten: 130, volts_lo_times_ten: 155, oil_hi: 220, oil_lo: 75, ..Default::default() };
+    let timestamp = Timestamp { month: 2, day: 30, year: 5, hour: 23, minute: 2, unknown: 0 };
+    let tail = U { tail: "N51SW".to_owned(), padding: "__".to_owned() };
+    let headers = [
+        tail.clone(), tail, A(limits), F(FuelFlowLimits::default()), T(timestamp),
+        D(FlightInfo { flight_number: 2, length: 10 }), D(FlightInfo { flight_number: 1, length: 0 }),
+        L(LastHeaderRecord::default()),
+    ];
+    assert_eq!(validate_headers(&headers), vec![
+        Warning::DuplicateRecord('U'),
+        Warning::MissingRecord('C'),
+        Warning::InvertedLimits { name: "volts", lo: 155, hi: 130 },
+        Warning::ZeroKFactor,
+        Warning::BadTimestamp(timestamp),
+        Warning::EmptyFlight(1),
+        Warning::FlightOrder { previous: 2, next: 1 },
+    ]);
+}
+
+#[test]
+fn test_validate() {
+    use crate::file::{test_download, test_flight, TEST_RECORDS};
+    use crate::headers::FLAG_RPM;
+
+    let good = test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS)]);
+    assert_eq!(validate(&JpiFile::from_bytes(good.clone()).unwrap()), vec![Warning::MissingRecord('A'), Warning::MissingRecord('F')]);
+
+    // the second flight's header says it's flight 7, and it had an RPM probe $C doesn't list
+    let bytes = test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS), test_flight(7, 0x1831F8FD | FLAG_RPM, &TEST_RECORDS)]);
+    let mut extra = bytes.clone();
+    extra.extend_from_slice(&[0, 0, 0]);
+    assert_eq!(validate(&JpiFile::from_bytes(extra).unwrap())[2..], [
+        Warning::FlightNumberMismatch { directory: 2, header: 7 },
+        Warning::FlagsDiffer { flight_number: 2, config: 0x1831F8FD, flight: 0x1831F8FD | FLAG_RPM },
+        Warning::TrailingData(3),
+    ]);
+    let short = JpiFile::from_bytes(bytes[..bytes.len() - 2].to_vec()).unwrap();
+    assert_eq!(validate(&short)[2], Warning::FlightTruncated { flight_number: 2, expected: 26, available: 24 });
+
+    // a header that stops without $L runs straight into the first flight
+    let l = good.windows(3).position(|w| w == b"$L,").unwrap();
+    let end = l + good[l..].iter().position(|&b| b == b'\n').unwrap() + 1;
+    let no_l = JpiFile::from_bytes([&good[..l], &good[end..]].concat()).unwrap();
+    assert!(validate(&no_l).contains(&Warning::MissingRecord('L')));
+    assert_eq!(no_l.decode_all().unwrap(), JpiFile::from_bytes(good).unwrap().decode_all().unwrap());
+}
+
+#[test]
+fn test_validate_corrupt_record() {
+    use crate::file::{test_download, test_flight, TEST_RECORDS};
+
+    // checksums fine, but gives RPM and its high byte both a sign, which only a corrupt record does
+    let corrupt: &[u8] = &[0x20, 0x20, 0, 0b110, 0b110, 1, 1];
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS), test_flight(2, 0x1831F8FD, &[corrupt])])).unwrap();
+    assert_eq!(validate(&file), vec![Warning::MissingRecord('A'), Warning::MissingRecord('F')]);
+    assert!(file.decode_flight(0).is_ok());
+    let error = file.decode_flight(1).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().starts_with("bad record"));
+}
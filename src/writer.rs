@@ -0,0 +1,99 @@
+use std::io::{self, Write};
+
+use crate::data::{flightheader, write_flight_header};
+use crate::file::JpiFile;
+use crate::headers::{FlightInfo, HeaderRecord};
+
+// writes a JPI download. `headers` is written in order except that its $D records are replaced by
+// a directory generated from `flights`, each of which is a flight number and the flight's raw
+// bytes starting at its flight header
+pub fn write_jpi<W: Write>(out: &mut W, headers: &[HeaderRecord], flights: &[(u16, &[u8])]) -> io::Result<()> {
+    // a $D newer firmware added fields to keeps them
+    let extra = |flight_number: u16| headers.iter().find_map(|h| match h {
+        HeaderRecord::Extended { record, extra } if matches!(**record, HeaderRecord::D(d) if d.flight_number == flight_number) =>
+            Some(extra.clone()),
+        _ => None
+    });
+    let directory = flights.iter().map(|(flight_number, data)| {
+        let record = HeaderRecord::D(FlightInfo {
+            flight_number: *flight_number,
+            length: data.len().div_ceil(2) as u16,
+        });
+        match extra(*flight_number) {
+            Some(extra) => HeaderRecord::Extended { record: Box::new(record), extra },
+            None => record
+        }
+    });
+
+    // the directory goes where the old one was, or just before $L if there wasn't one
+    let is_d = |h: &&HeaderRecord| matches!(h.record(), HeaderRecord::D(_));
+    let at = headers.iter().position(|h| is_d(&h))
+        .or_else(|| headers.iter().position(|h| matches!(h.record(), HeaderRecord::L(_))))
+        .unwrap_or(headers.len());
+
+    for record in headers[..at].iter().filter(|h| !is_d(h)) {
+        write!(out, "{}\r\n", record)?;
+    }
+    for record in directory {
+        write!(out, "{}\r\n", record)?;
+    }
+    for record in headers[at..].iter().filter(|h| !is_d(h)) {
+        write!(out, "{}\r\n", record)?;
+    }
+
+    for (_, data) in flights {
+        out.write_all(data)?;
+        if data.len() % 2 == 1 { // flights are stored as whole words
+            out.write_all(&[0])?;
+        }
+    }
+
+    Ok(())
+}
+
+// a standalone download holding only the flights at `indices`, with the original $U/$A/$F/$T/$C
+// headers
+pub fn extract<W: Write>(out: &mut W, file: &JpiFile, indices: &[usize]) -> io::Result<()> {
+    rewrite(out, file, file.headers(), indices, |_| Ok(()))
+}
+
+// writes the flights at `indices` under a new set of headers, letting `edit` change each flight
+// header on the way or refuse to. flights are decoded first so a corrupt one isn't passed along, and their
+// flight headers are re-encoded with fresh checksums
+pub fn rewrite<W, F>(out: &mut W, file: &JpiFile, headers: &[HeaderRecord], indices: &[usize], edit: F) -> io::Result<()>
+    where W: Write, F: Fn(&mut flightheader) -> io::Result<()> {
+    let mut flights = Vec::with_capacity(indices.len());
+    for &i in indices {
+        let mut flight = file.decode_flight(i)?;
+        edit(&mut flight.header)?;
+
+        let mut data = file.flight_data(i).to_vec();
+        let header = write_flight_header(&flight.header, file.protocol().checksum);
+        data[..header.len()].copy_from_slice(&header);
+        flights.push((flight.info.flight_number, data));
+    }
+
+    let flights = flights.iter().map(|(n, data)| (*n, data.as_slice())).collect::<Vec<_>>();
+    write_jpi(out, headers, &flights)
+}
+
+#[test]
+fn test_extract() {
+    use crate::file::{test_download, test_flight, TEST_RECORDS};
+
+    let third: [&[u8]; 2] = [&[1, 1, 0, 0b1, 0, 20], &[0, 0, 1]];
+    let file = JpiFile::from_bytes(test_download(&[test_flight(1, 0x1831F8FD, &TEST_RECORDS),
+                                                   test_flight(2, 0x1831F8FD, &TEST_RECORDS[..1]),
+                                                   test_flight(3, 0x1831F8FD, &third)])).unwrap();
+    let mut bytes = Vec::new();
+    extract(&mut bytes, &file, &[0, 2]).unwrap();
+    let extracted = JpiFile::from_bytes(bytes).unwrap();
+
+    let not_d = |h: &&HeaderRecord| !matches!(h.record(), HeaderRecord::D(_));
+    assert!(extracted.headers().iter().filter(not_d).eq(file.headers().iter().filter(not_d)));
+    let kept = [0, 2].iter().map(|&i| (i, *file.flights().nth(i).unwrap())).collect::<Vec<_>>();
+    assert!(extracted.flights().copied().eq(kept.iter().map(|(_, info)| *info)));
+    for (n, (i, _)) in kept.iter().enumerate() {
+        assert_eq!(extracted.decode_flight(n).unwrap(), file.decode_flight(*i).unwrap());
+    }
+}